@@ -1,21 +1,22 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
-use std::net::SocketAddr;
+use std::net::IpAddr;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 
 use axum::Router;
 use bevy_app::prelude::*;
 use bevy_ecs::prelude::*;
-use hotwatch::blocking::{Flow, Hotwatch};
+use bevy_hierarchy::prelude::*;
+use hotwatch::Hotwatch;
 use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
-use slog::{error, info, o, Drain};
+use slog::{error, info, o, warn, Drain};
 use structopt::StructOpt;
 use tera::Tera;
-use tower_http::services::ServeDir;
+use tower_http::services::{ServeDir, ServeFile};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 enum SourceType {
     // File will be copied to the corresponding path in the output dir directly
     StaticContent,
@@ -27,12 +28,22 @@ enum SourceType {
     DynamicContentBlogPost,
     // File will be loaded as a template for tag pages
     DynamicContentBlogpostTagPage,
+    // File will be loaded as a template for series landing pages
+    DynamicContentBlogpostSeriesPage,
+    // File will be loaded as a template for author pages (or feeds)
+    DynamicContentBlogpostAuthorPage,
+    // File will be loaded as a template spawning one page per data record
+    DynamicContentDataPage,
     // File will be loaded as a template for archive pages
     DynamicContentBlogpostArchivePage,
     // File will be loaded as an RSS template
     DynamicContentBlogpostRssPage,
     // File will be loaded as sitemap page
     DynamicContentSitemap,
+    // File will be compiled, minified and fingerprinted as a CSS/JS asset
+    Asset,
+    // File will be loaded as JSON/YAML/TOML/CSV data available to templates
+    Data,
 }
 
 #[derive(Debug, Clone, Component, Deserialize, PartialEq, Eq)]
@@ -43,6 +54,12 @@ enum DynamicContentType {
     Blogpost,
     // A tag page
     BlogpostTagPage,
+    // A series landing page
+    BlogpostSeriesPage,
+    // An author page
+    BlogpostAuthorPage,
+    // A page for a single record from a data file
+    DataPage,
     // An archive page
     BlogpostArchivePage,
     // An rss page
@@ -52,7 +69,8 @@ enum DynamicContentType {
 }
 
 // Immutable config loaded from the user
-#[derive(Clone, Resource, Debug, Deserialize)]
+#[derive(Clone, Resource, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct Config {
     source_dir: PathBuf,
     output_dir: PathBuf,
@@ -61,6 +79,207 @@ struct Config {
     routes: HashMap<String, String>,
     blogpost_template: String,
     site_url: String,
+    #[serde(default)]
+    assets: AssetConfig,
+    // Minify rendered HTML pages before writing them out
+    #[serde(default)]
+    minify_html: bool,
+    #[serde(default)]
+    compression: CompressionConfig,
+    #[serde(default)]
+    markdown: MarkdownConfig,
+    // Most related posts given to each blogpost
+    #[serde(default = "default_related_posts_limit")]
+    related_posts_limit: usize,
+    // Authors blogposts can be attributed to, keyed by id
+    #[serde(default)]
+    authors: BTreeMap<String, Author>,
+    #[serde(default)]
+    trailing_slash: TrailingSlash,
+    // Map from old paths to where they should redirect to
+    #[serde(default)]
+    redirects: BTreeMap<String, String>,
+    #[serde(default)]
+    redirect_files: RedirectFilesConfig,
+    // Template rendered to 404.html, served for missing pages
+    #[serde(default)]
+    not_found_template: Option<String>,
+    #[serde(default)]
+    seo: SeoConfig,
+    // Generate preview images for pages without an og_image
+    #[serde(default)]
+    social_images: Option<SocialImageConfig>,
+    #[serde(default)]
+    robots_txt: Option<RobotsTxtConfig>,
+    #[serde(default)]
+    security_txt: Option<SecurityTxtConfig>,
+    // Generate humans.txt from the authors
+    #[serde(default)]
+    humans_txt: bool,
+    // Directory with templates, static files and a default config to share between sites
+    #[serde(default)]
+    theme: Option<PathBuf>,
+    // Profile the config was loaded with
+    #[serde(skip)]
+    profile: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RobotsTxtConfig {
+    // Keep crawlers away from the whole site, e.g. for staging
+    #[serde(default)]
+    disallow_all: bool,
+    #[serde(default)]
+    disallow: Vec<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+}
+
+// Fields for .well-known/security.txt, see RFC 9116
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct SecurityTxtConfig {
+    // e.g. mailto:security@example.com
+    contact: Vec<String>,
+    // e.g. 2030-01-01T00:00:00Z
+    expires: String,
+    #[serde(default)]
+    encryption: Vec<String>,
+    #[serde(default)]
+    acknowledgments: Vec<String>,
+    #[serde(default)]
+    preferred_languages: Option<String>,
+    #[serde(default)]
+    policy: Vec<String>,
+    #[serde(default)]
+    hiring: Vec<String>,
+}
+
+// Settings for generated social preview images
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct SocialImageConfig {
+    // SVG template, gets title, sitename, date and background in its context
+    template: String,
+    // Where in the output directory the images go
+    #[serde(default = "default_social_image_dir")]
+    output_dir: String,
+    // Relative to the source dir
+    #[serde(default)]
+    background: Option<String>,
+    // Font files to load on top of the system fonts
+    #[serde(default)]
+    fonts: Vec<PathBuf>,
+}
+
+fn default_social_image_dir() -> String {
+    "social".to_string()
+}
+
+// Site wide defaults for SEO metadata
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct SeoConfig {
+    // Image used for social previews when a page doesn't have its own
+    #[serde(default)]
+    default_image: Option<String>,
+    // Twitter handle of the site, e.g. @example
+    #[serde(default)]
+    twitter_site: Option<String>,
+}
+
+// Server config files listing all redirects, for hosts that can do real redirects
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RedirectFilesConfig {
+    // Netlify/Cloudflare Pages style _redirects file
+    #[serde(default)]
+    netlify: bool,
+    // nginx location blocks, to be included in a server block
+    #[serde(default)]
+    nginx: bool,
+}
+
+// What to do with the trailing slash of URLs for pages (i.e. without an extension)
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+enum TrailingSlash {
+    // Keep whatever the route has
+    #[default]
+    Preserve,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Author {
+    // Filled in from the key in the config
+    #[serde(skip_deserializing)]
+    id: String,
+    name: String,
+    #[serde(default)]
+    bio: String,
+    #[serde(default)]
+    avatar: Option<String>,
+    // e.g. "github" => "https://github.com/..."
+    #[serde(default)]
+    links: BTreeMap<String, String>,
+}
+
+// Settings for rendering markdown content
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct MarkdownConfig {
+    // Add a link to itself next to every heading
+    #[serde(default)]
+    heading_anchors: bool,
+    // Used to estimate reading time
+    #[serde(default = "default_words_per_minute")]
+    words_per_minute: usize,
+}
+
+fn default_words_per_minute() -> usize {
+    200
+}
+
+fn default_related_posts_limit() -> usize {
+    5
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            heading_anchors: false,
+            words_per_minute: default_words_per_minute(),
+        }
+    }
+}
+
+// Precompressed variants to write next to text outputs
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CompressionConfig {
+    #[serde(default)]
+    gzip: bool,
+    #[serde(default)]
+    brotli: bool,
+}
+
+// Settings for the asset pipeline
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct AssetConfig {
+    // Asset names (as used with asset_url) are relative to this directory
+    #[serde(default)]
+    root: PathBuf,
+    #[serde(default)]
+    minify: bool,
+    #[serde(default)]
+    fingerprint: bool,
+    // Map from bundle name (relative to root) to the globs concatenated into it
+    #[serde(default)]
+    bundles: BTreeMap<String, Vec<String>>,
 }
 
 #[derive(Component)]
@@ -73,6 +292,16 @@ struct LoadTemplateGlob {
     glob: String,
 }
 
+#[derive(Component)]
+struct LoadAssetGlob {
+    glob: String,
+}
+
+#[derive(Component)]
+struct LoadDataGlob {
+    glob: String,
+}
+
 #[derive(Component)]
 struct LoadDynamicContentGlob {
     glob: String,
@@ -110,6 +339,24 @@ fn create_source_loaders(config: Res<Config>, mut commands: Commands) {
                     type_: DynamicContentType::BlogpostTagPage,
                 });
             }
+            SourceType::DynamicContentBlogpostSeriesPage => {
+                commands.spawn_empty().insert(LoadDynamicContentGlob {
+                    glob: glob.clone(),
+                    type_: DynamicContentType::BlogpostSeriesPage,
+                });
+            }
+            SourceType::DynamicContentBlogpostAuthorPage => {
+                commands.spawn_empty().insert(LoadDynamicContentGlob {
+                    glob: glob.clone(),
+                    type_: DynamicContentType::BlogpostAuthorPage,
+                });
+            }
+            SourceType::DynamicContentDataPage => {
+                commands.spawn_empty().insert(LoadDynamicContentGlob {
+                    glob: glob.clone(),
+                    type_: DynamicContentType::DataPage,
+                });
+            }
             SourceType::DynamicContentBlogpostArchivePage => {
                 commands.spawn_empty().insert(LoadDynamicContentGlob {
                     glob: glob.clone(),
@@ -128,6 +375,16 @@ fn create_source_loaders(config: Res<Config>, mut commands: Commands) {
                     type_: DynamicContentType::SitemapPage,
                 });
             }
+            SourceType::Asset => {
+                commands
+                    .spawn_empty()
+                    .insert(LoadAssetGlob { glob: glob.clone() });
+            }
+            SourceType::Data => {
+                commands
+                    .spawn_empty()
+                    .insert(LoadDataGlob { glob: glob.clone() });
+            }
         }
     }
 }
@@ -142,6 +399,14 @@ fn make_relative(path: &Path, base: &Path) -> PathBuf {
     .to_path_buf()
 }
 
+// Glob for everything in a directory of the theme, if there is one
+fn theme_glob(config: &Config, dir: &str) -> Option<String> {
+    config
+        .theme
+        .as_ref()
+        .map(|theme| theme.join(dir).join("**/*").to_string_lossy().to_string())
+}
+
 fn static_content_source_loader(
     config: Res<Config>,
     query: Query<&LoadStaticContentGlob>,
@@ -152,13 +417,31 @@ fn static_content_source_loader(
             .unwrap_or_else(|_| panic!("Unable to read glob: {}", &glob.glob))
             .filter_map(|p| p.ok())
     });
-    for path in paths {
-        let relative = make_relative(&path, config.source_dir.as_path());
+    // Pairs of (source, path relative to the output dir)
+    let mut files: Vec<_> = paths
+        .map(|path| {
+            let relative = make_relative(&path, config.source_dir.as_path());
+            (relative.clone(), relative)
+        })
+        .collect();
+    // Theme files are only used if the site doesn't have its own at the same path
+    if let (Some(theme), Some(glob)) = (config.theme.as_ref(), theme_glob(&config, "static")) {
+        let theme_files: Vec<_> = glob::glob(&glob)
+            .unwrap_or_else(|_| panic!("Unable to read glob: {}", &glob))
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+            .map(|path| {
+                let relative = make_relative(&path, theme);
+                (path, relative)
+            })
+            .filter(|(_, relative)| !files.iter().any(|(_, r)| r == relative))
+            .collect();
+        files.extend(theme_files);
+    }
+    for (source, relative) in files {
         commands
             .spawn_empty()
-            .insert(RelativeSourcePath {
-                path: relative.clone(),
-            })
+            .insert(RelativeSourcePath { path: source })
             .insert(URL {
                 url: relative.to_string_lossy().to_string(),
                 absolute: format!("{}{}", config.site_url, relative.to_string_lossy()),
@@ -189,25 +472,319 @@ impl DerefMut for TeraResource {
     }
 }
 
-fn template_source_loader(query: Query<&LoadTemplateGlob>, mut commands: Commands) {
-    let mut iter = query.iter();
-    let tera = iter.next().map(|glob| {
-        Tera::new(&glob.glob)
-            .unwrap_or_else(|_| panic!("Unable to load templates from {}", glob.glob))
-    });
-    if tera.is_none() {
-        return;
+// Site templates override theme templates with the same name, and can extend them
+fn load_templates<'a>(
+    config: &Config,
+    globs: impl Iterator<Item = &'a String>,
+) -> Result<Tera, String> {
+    let globs: Vec<_> = theme_glob(config, "templates")
+        .into_iter()
+        .chain(globs.cloned())
+        .collect();
+    // Later files replace earlier ones with the same name
+    let mut files = vec![];
+    for glob in &globs {
+        let parsed = Tera::parse(glob)
+            .map_err(|e| format!("Unable to load templates from {}: {}", glob, e))?;
+        files.extend(parsed.get_template_names().filter_map(|name| {
+            let path = parsed.get_template(name).ok()?.path.clone()?;
+            Some((path, Some(name.to_string())))
+        }));
     }
-    let mut tera = tera.unwrap();
-    for glob in iter {
-        let new = Tera::new(&glob.glob)
-            .unwrap_or_else(|_| panic!("Unable to load templates from {}", glob.glob));
-        tera.extend(&new)
-            .unwrap_or_else(|_| panic!("Unable to extend with templates from {}", glob.glob));
+    let mut tera = Tera::default();
+    tera.add_template_files(files)
+        .map_err(|e| format!("Unable to load templates: {}", e))?;
+    Ok(tera)
+}
+
+fn template_source_loader(
+    config: Res<Config>,
+    query: Query<&LoadTemplateGlob>,
+    mut commands: Commands,
+) {
+    if query.is_empty() && config.theme.is_none() {
+        return;
     }
+    let tera =
+        load_templates(&config, query.iter().map(|g| &g.glob)).unwrap_or_else(|e| panic!("{}", e));
     commands.insert_resource(TeraResource { tera });
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AssetKind {
+    Css,
+    Scss,
+    Js,
+    // Anything else is fingerprinted but copied as is
+    Other,
+}
+
+impl AssetKind {
+    fn from_path(path: &Path) -> AssetKind {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("css") => AssetKind::Css,
+            Some("scss") | Some("sass") => AssetKind::Scss,
+            Some("js") => AssetKind::Js,
+            _ => AssetKind::Other,
+        }
+    }
+}
+
+// An asset to be processed and written out
+#[derive(Component, Debug)]
+struct AssetSource {
+    // Name the asset is looked up by in templates
+    name: String,
+    kind: AssetKind,
+    // Files making up the asset, concatenated in order
+    parts: Vec<PathBuf>,
+}
+
+fn asset_name(config: &Config, relative: &Path) -> String {
+    let mut name = relative
+        .strip_prefix(&config.assets.root)
+        .unwrap_or(relative)
+        .to_path_buf();
+    if AssetKind::from_path(&name) == AssetKind::Scss {
+        name.set_extension("css");
+    }
+    name.to_string_lossy().to_string()
+}
+
+fn asset_source_loader(config: Res<Config>, query: Query<&LoadAssetGlob>, mut commands: Commands) {
+    let paths = query.iter().flat_map(|glob| {
+        glob::glob(&glob.glob)
+            .unwrap_or_else(|_| panic!("Unable to read glob: {}", &glob.glob))
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+    });
+    for path in paths {
+        let relative = make_relative(&path, config.source_dir.as_path());
+        let kind = AssetKind::from_path(&relative);
+        // SCSS partials only exist to be imported by other files
+        let is_partial = relative
+            .file_name()
+            .map(|n| n.to_string_lossy().starts_with('_'))
+            .unwrap_or(false);
+        if kind == AssetKind::Scss && is_partial {
+            continue;
+        }
+        commands
+            .spawn_empty()
+            .insert(AssetSource {
+                name: asset_name(&config, &relative),
+                kind,
+                parts: vec![relative.clone()],
+            })
+            .insert(RelativeSourcePath { path: relative });
+    }
+    for (name, globs) in &config.assets.bundles {
+        let kind = AssetKind::from_path(Path::new(name));
+        if kind != AssetKind::Css && kind != AssetKind::Js {
+            panic!("Asset bundle {} must be a .css or .js file!", name);
+        }
+        let parts: Vec<_> = globs
+            .iter()
+            .flat_map(|glob| {
+                glob::glob(glob)
+                    .unwrap_or_else(|_| panic!("Unable to read glob: {}", glob))
+                    .filter_map(|p| p.ok())
+                    .filter(|p| p.is_file())
+                    .sorted()
+            })
+            .map(|p| make_relative(&p, config.source_dir.as_path()))
+            .collect();
+        if parts.is_empty() {
+            panic!("Asset bundle {} matched no files!", name);
+        }
+        commands.spawn_empty().insert(AssetSource {
+            name: name.clone(),
+            kind,
+            parts,
+        });
+    }
+}
+
+fn compile_asset_part(path: &Path) -> String {
+    match AssetKind::from_path(path) {
+        AssetKind::Scss => grass::from_path(path, &grass::Options::default())
+            .unwrap_or_else(|e| panic!("Unable to compile {}: {}", path.to_string_lossy(), e)),
+        _ => std::fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Unable to read file {}", path.to_string_lossy())),
+    }
+}
+
+fn minify_asset(name: &str, kind: AssetKind, contents: &str) -> String {
+    match kind {
+        AssetKind::Css | AssetKind::Scss => minifier::css::minify(contents)
+            .unwrap_or_else(|e| panic!("Unable to minify {}: {}", name, e))
+            .to_string(),
+        AssetKind::Js => minifier::js::minify(contents)
+            .unwrap_or_else(|e| panic!("Unable to minify {}: {}", name, e))
+            .to_string(),
+        AssetKind::Other => contents.to_string(),
+    }
+}
+
+// Inserts a short content hash before the extension, e.g. site.css -> site.3fa9c1d2.css
+fn fingerprinted_name(name: &str, contents: &[u8]) -> String {
+    use sha2::Digest;
+    let hash: String = sha2::Sha256::digest(contents)
+        .iter()
+        .take(4)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, hash, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    };
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+// Map from asset names to the URLs they were written to
+#[derive(Resource, Debug, Default)]
+struct AssetManifest {
+    entries: BTreeMap<String, String>,
+}
+
+fn asset_processor(
+    config: Res<Config>,
+    query: Query<(Entity, &AssetSource)>,
+    mut commands: Commands,
+) {
+    let mut manifest = AssetManifest::default();
+    for (entity, asset) in query.iter() {
+        let contents = if asset.kind == AssetKind::Other {
+            None
+        } else {
+            let contents = asset.parts.iter().map(|p| compile_asset_part(p)).join("\n");
+            Some(if config.assets.minify {
+                minify_asset(&asset.name, asset.kind, &contents)
+            } else {
+                contents
+            })
+        };
+        let name = if config.assets.fingerprint {
+            let bytes = match contents.as_ref() {
+                Some(contents) => contents.as_bytes().to_vec(),
+                None => std::fs::read(&asset.parts[0]).unwrap_or_else(|_| {
+                    panic!("Unable to read file {}", asset.parts[0].to_string_lossy())
+                }),
+            };
+            fingerprinted_name(&asset.name, &bytes)
+        } else {
+            asset.name.clone()
+        };
+        let path = config.assets.root.join(name);
+        let url = format!("/{}", path.to_string_lossy());
+        manifest.entries.insert(asset.name.clone(), url.clone());
+        let mut builder = commands.entity(entity);
+        builder
+            .insert(URL {
+                absolute: format!("{}{}", config.site_url, url),
+                url,
+            })
+            .insert(RelativeOutputPath { path })
+            .insert(IsStaticContent {})
+            .insert(ExcludeFromSitemap {});
+        match contents {
+            Some(contents) => builder.insert(WriteContentsToFile { contents }),
+            None => builder.insert(CopySourceToOutput {}),
+        };
+    }
+    commands.insert_resource(manifest);
+}
+
+struct AssetUrl {
+    entries: BTreeMap<String, String>,
+}
+
+impl tera::Function for AssetUrl {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let path = match args.get("path") {
+            Some(val) => tera::from_value::<String>(val.clone())
+                .map_err(|_| tera::Error::msg("invalid path")),
+            None => Err(tera::Error::msg("missing path")),
+        }?;
+        let url = self
+            .entries
+            .get(&path)
+            .ok_or_else(|| tera::Error::msg(format!("unknown asset: {}", path)))?;
+        Ok(tera::to_value(url)?)
+    }
+}
+
+// Structured data loaded from data files, keyed by file stem
+#[derive(Resource, Debug, Default)]
+struct SiteData {
+    entries: BTreeMap<String, Value>,
+}
+
+const DATA_EXTENSIONS: [&str; 5] = ["json", "yaml", "yml", "toml", "csv"];
+
+// Parses JSON/YAML/TOML/CSV, based on the extension
+fn parse_data_file(path: &Path) -> Result<Value, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|_| format!("Unable to read file {}", path.to_string_lossy()))?;
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    let parsed = match extension.as_deref() {
+        Some("json") => serde_json::from_str(&source).map_err(|e| e.to_string()),
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&source).map_err(|e| e.to_string()),
+        Some("toml") => toml::from_str(&source).map_err(|e| e.to_string()),
+        // Each row becomes an object keyed by the header
+        Some("csv") => csv::Reader::from_reader(source.as_bytes())
+            .deserialize::<BTreeMap<String, String>>()
+            .map(|row| row.map(|row| tera::to_value(row).unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array)
+            .map_err(|e| e.to_string()),
+        _ => Err("unsupported data file format".to_string()),
+    };
+    parsed.map_err(|e| format!("Could not parse {}: {}", path.to_string_lossy(), e))
+}
+
+fn data_source_loader(query: Query<&LoadDataGlob>, mut commands: Commands) {
+    // Hidden files are left alone, so editor and OS files don't end up as data
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    };
+    let paths = query.iter().flat_map(|glob| {
+        glob::glob_with(&glob.glob, options)
+            .unwrap_or_else(|_| panic!("Unable to read glob: {}", &glob.glob))
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+    });
+    let paths = paths.filter(|path| {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let supported = extension.is_some_and(|e| DATA_EXTENSIONS.contains(&e.as_str()));
+        if !supported {
+            eprintln!("Skipping {}, it's not a data file", path.to_string_lossy());
+        }
+        supported
+    });
+    let mut data = SiteData::default();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .unwrap_or_else(|| panic!("Path must have a stem: {}", path.to_string_lossy()))
+            .to_string_lossy()
+            .to_string();
+        if data.entries.contains_key(&name) {
+            panic!(
+                "Data file {} clashes with another data file named {}!",
+                path.to_string_lossy(),
+                name
+            );
+        }
+        let value = parse_data_file(&path).unwrap_or_else(|e| panic!("{}", e));
+        data.entries.insert(name, value);
+    }
+    commands.insert_resource(data);
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct NavbarConfig {
     // Index within the group
@@ -219,6 +796,10 @@ struct NavbarConfig {
     group: Option<String>,
     #[serde(default)]
     is_primary: bool,
+    // For primary entries, the group this whole group is nested in.
+    // If not set, the group is shown in the top level navbar
+    #[serde(default)]
+    parent_group: Option<String>,
 }
 
 // TODO: This should not be a component, just a config
@@ -240,8 +821,23 @@ struct DynamicContentMetadata {
     og_type: String,
     #[serde(default)]
     og_description: String,
+    // Falls back to the configured default image
+    #[serde(default)]
+    og_image: Option<String>,
+    // Falls back to the page's own URL
+    #[serde(default)]
+    canonical_url: Option<String>,
+    // Falls back to summary_large_image if there's an image, summary otherwise
+    #[serde(default)]
+    twitter_card: Option<String>,
     #[serde(default)]
     exclude_from_sitemap: bool,
+    // Opt out of HTML minification for this page
+    #[serde(default)]
+    skip_minify_html: bool,
+    // Old paths this page used to be at, which redirect here
+    #[serde(default)]
+    aliases: Vec<String>,
 }
 
 #[derive(Debug, Clone, Component)]
@@ -249,6 +845,107 @@ struct DynamicContentContents {
     contents: String,
 }
 
+// Everything before this in a markdown page is used as its excerpt
+const EXCERPT_MARKER: &str = "<!-- more -->";
+
+// Plain text of each block in some markdown, and whether that block is a paragraph
+fn markdown_text_blocks(contents: &str) -> Vec<(bool, String)> {
+    use pulldown_cmark::{Event, TagEnd};
+    let mut blocks = vec![];
+    let mut current = String::new();
+    for event in pulldown_cmark::Parser::new_ext(contents, pulldown_cmark::Options::empty()) {
+        match event {
+            Event::Text(text) | Event::Code(text) => current.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => current.push(' '),
+            Event::End(
+                end @ (TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Item),
+            ) => {
+                let text = std::mem::take(&mut current).split_whitespace().join(" ");
+                blocks.push((end == TagEnd::Paragraph, text));
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+// Computed fields for a markdown page
+struct MarkdownStatistics {
+    word_count: usize,
+    // In minutes
+    reading_time: usize,
+    excerpt: String,
+}
+
+fn markdown_statistics(config: &MarkdownConfig, contents: &str) -> MarkdownStatistics {
+    let blocks = markdown_text_blocks(contents);
+    let word_count: usize = blocks
+        .iter()
+        .map(|(_, text)| text.split_whitespace().count())
+        .sum();
+    let reading_time = word_count.div_ceil(config.words_per_minute.max(1)).max(1);
+    let excerpt = match contents.find(EXCERPT_MARKER) {
+        Some(end) => markdown_text_blocks(&contents[..end])
+            .into_iter()
+            .map(|(_, text)| text)
+            .join(" "),
+        None => blocks
+            .into_iter()
+            .find(|(is_paragraph, _)| *is_paragraph)
+            .map(|(_, text)| text)
+            .unwrap_or_default(),
+    };
+    MarkdownStatistics {
+        word_count,
+        reading_time,
+        excerpt,
+    }
+}
+
+// Ids of the authors of a page
+fn metadata_authors(metadata: &DynamicContentMetadata) -> Vec<String> {
+    metadata
+        .stuff
+        .get("authors")
+        .and_then(|a| a.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|a| a.as_str())
+                .map(|a| a.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Splits a dynamic content file into its JSON metadata and contents
+fn read_dynamic_content(path: &Path) -> Result<(DynamicContentMetadata, String), String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|_| format!("Unable to read file {}", path.to_string_lossy()))?;
+    if !source.starts_with("{\n") {
+        return Err(format!(
+            "Metadata in {} must start with {{ on its own line!",
+            path.to_string_lossy()
+        ));
+    }
+    let token = "\n}\n\n";
+    let split = source.find(token).ok_or_else(|| {
+        format!(
+            "Need terminator for metadata in {}!",
+            path.to_string_lossy()
+        )
+    })?;
+    let metadata = serde_json::from_str(&source[0..split + 2]).map_err(|e| {
+        format!(
+            "Could not parse metadata in {}: {:?}",
+            path.to_string_lossy(),
+            e
+        )
+    })?;
+    // TODO: See if we can avoid the copy here
+    let contents = source[split + token.len()..].to_string();
+    Ok((metadata, contents))
+}
+
 fn dynamic_content_source_loader(
     config: Res<Config>,
     query: Query<&LoadDynamicContentGlob>,
@@ -263,33 +960,32 @@ fn dynamic_content_source_loader(
     // TODO: Make this parallel somehow to speed up I/O
     for (type_, path) in paths {
         let relative = make_relative(&path, config.source_dir.as_path());
-        let source = std::fs::read_to_string(&path)
-            .unwrap_or_else(|_| panic!("Unable to read file {}", path.to_string_lossy()));
-        assert!(source.starts_with("{\n"));
-        let token = "\n}\n\n";
-        let split = source.find(token).unwrap_or_else(|| {
-            panic!(
-                "Need terminator for metadata in {}!",
-                path.to_string_lossy()
-            )
-        });
-        let mut metadata: DynamicContentMetadata = serde_json::from_str(&source[0..split + 2])
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Could not parse metadata in {}: {:?}",
-                    path.to_string_lossy(),
-                    e
-                )
-            });
-        // TODO: See if we can avoid the copy here
-        let contents = source[split + token.len()..].to_string();
+        let (mut metadata, contents) =
+            read_dynamic_content(&path).unwrap_or_else(|e| panic!("{}", e));
         let exclude_from_sitemap = metadata.exclude_from_sitemap;
-        match type_ {
-            DynamicContentType::Blogpost => {
-                metadata.markdown = true;
-                metadata.template = Some(config.blogpost_template.clone());
-                metadata.stuff.insert(
-                    "slug".to_string(),
+        if metadata.markdown || type_ == DynamicContentType::Blogpost {
+            // Shortcodes aren't part of the prose
+            let prose =
+                replace_shortcodes(&contents, &parse_shortcodes(&contents), |_| String::new());
+            let statistics = markdown_statistics(&config.markdown, &prose);
+            metadata
+                .stuff
+                .insert("word_count".to_string(), statistics.word_count.into());
+            metadata
+                .stuff
+                .insert("reading_time".to_string(), statistics.reading_time.into());
+            // Hand written excerpts take priority
+            metadata
+                .stuff
+                .entry("excerpt".to_string())
+                .or_insert_with(|| statistics.excerpt.into());
+        }
+        match type_ {
+            DynamicContentType::Blogpost => {
+                metadata.markdown = true;
+                metadata.template = Some(config.blogpost_template.clone());
+                metadata.stuff.insert(
+                    "slug".to_string(),
                     relative
                         .file_stem()
                         .unwrap_or_else(|| {
@@ -339,6 +1035,20 @@ fn dynamic_content_source_loader(
                         )
                     }
                 }
+                // Allow a single author as a shorthand
+                if let Some(author @ Value::String(_)) = metadata.stuff.get("authors") {
+                    let authors = Value::Array(vec![author.clone()]);
+                    metadata.stuff.insert("authors".to_string(), authors);
+                }
+                for author in metadata_authors(&metadata) {
+                    if !config.authors.contains_key(&author) {
+                        panic!(
+                            "Blogpost at {} has unknown author {}!",
+                            relative.as_path().to_string_lossy(),
+                            author
+                        );
+                    }
+                }
                 metadata.og_type = "article".to_string();
                 if let Some(excerpt) = metadata.stuff.get("excerpt") {
                     metadata.og_description =
@@ -347,6 +1057,9 @@ fn dynamic_content_source_loader(
             }
             DynamicContentType::SinglePage
             | DynamicContentType::BlogpostTagPage
+            | DynamicContentType::BlogpostSeriesPage
+            | DynamicContentType::BlogpostAuthorPage
+            | DynamicContentType::DataPage
             | DynamicContentType::BlogpostArchivePage
             | DynamicContentType::BlogpostRssPage
             | DynamicContentType::SitemapPage => {}
@@ -363,6 +1076,33 @@ fn dynamic_content_source_loader(
     }
 }
 
+// The 404 page isn't a route, it's always at the same place
+fn not_found_page_loader(config: Res<Config>, mut commands: Commands) {
+    let template = match config.not_found_template.as_ref() {
+        Some(template) => template,
+        None => return,
+    };
+    let metadata: DynamicContentMetadata = serde_json::from_value(serde_json::json!({
+        "route": "404",
+        "title": "Page not found",
+        "template": template,
+    }))
+    .expect("Couldn't create metadata for the 404 page!");
+    let url = "/404.html".to_string();
+    commands
+        .spawn_empty()
+        .insert(URL {
+            absolute: format!("{}{}", config.site_url, url),
+            url,
+        })
+        .insert(metadata)
+        .insert(DynamicContentContents {
+            contents: String::new(),
+        })
+        .insert(DynamicContentType::SinglePage)
+        .insert(ExcludeFromSitemap {});
+}
+
 // URL (identifier) where this path will be at
 // TODO: Maybe allow the single page ones to define routes inline
 #[derive(Component, Debug)]
@@ -372,40 +1112,112 @@ struct URL {
     absolute: String,
 }
 
-fn url_for_impl(config: &Config, route: &String, replacements: &HashMap<String, Value>) -> URL {
-    let mut url = config
-        .routes
-        .get(route)
-        .unwrap_or_else(|| panic!("No route defined for {}", route))
-        .clone();
-    // Dynamic routes might need things replaced in from the stuff
-    for (key, value) in replacements.iter() {
-        if !url.contains('{') {
-            break;
-        }
-        let to_replace = format!("{{{}}}", key);
-        if let Some(value) = value.as_i64() {
-            url = url.replace(&to_replace, &value.to_string());
-        } else if let Some(value) = value.as_str() {
-            url = url.replace(&to_replace, value);
+// Formats a single placeholder value, applying the filter if there is one
+fn permalink_value(key: &str, filter: Option<&str>, value: &Value) -> Result<String, String> {
+    let value = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => {
+            return Err(format!(
+                "placeholder {{{}}} must be a string, number or bool, got {}",
+                key, value
+            ))
+        }
+    };
+    match filter {
+        None => Ok(value),
+        Some("slug") => Ok(slugify(&value)),
+        Some("lower") => Ok(value.to_lowercase()),
+        Some(filter) => Err(format!(
+            "unknown filter {} for placeholder {{{}}}",
+            filter, key
+        )),
+    }
+}
+
+// Fills in `{key}` and `{key|filter}` placeholders in a route pattern
+fn expand_permalink<'a>(
+    pattern: &str,
+    lookup: impl Fn(&str) -> Option<&'a Value>,
+) -> Result<String, String> {
+    let mut url = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| "unterminated placeholder".to_string())?;
+        let placeholder = &rest[start + 1..end];
+        let (key, filter) = match placeholder.split_once('|') {
+            Some((key, filter)) => (key.trim(), Some(filter.trim())),
+            None => (placeholder.trim(), None),
         };
+        let value = lookup(key).ok_or_else(|| format!("no value for placeholder {{{}}}", key))?;
+        let value = permalink_value(key, filter, value)?;
+        if value.is_empty() {
+            return Err(format!("placeholder {{{}}} is empty", key));
+        }
+        url.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    if rest.contains('}') {
+        return Err("unmatched }".to_string());
+    }
+    url.push_str(rest);
+    Ok(url)
+}
+
+fn apply_trailing_slash(policy: TrailingSlash, mut url: String) -> String {
+    let is_page = Path::new(&url).extension().is_none();
+    match policy {
+        TrailingSlash::Always if is_page && !url.ends_with('/') => url.push('/'),
+        TrailingSlash::Never if is_page && url.len() > 1 => {
+            url.truncate(url.trim_end_matches('/').len().max(1))
+        }
+        _ => {}
     }
-    assert!(!url.contains('{'), "URL should be fully generated: {}", url);
+    url
+}
+
+fn url_for_impl<'a>(
+    config: &Config,
+    route: &str,
+    lookup: impl Fn(&str) -> Option<&'a Value>,
+) -> Result<URL, String> {
+    let pattern = config
+        .routes
+        .get(route)
+        .ok_or_else(|| format!("No route defined for {}", route))?;
+    let url = expand_permalink(pattern, lookup)
+        .map_err(|e| format!("Could not generate URL for route {}: {}", route, e))?;
+    let url = apply_trailing_slash(config.trailing_slash, url);
     let absolute = format!("{}{}", config.site_url, url);
-    URL { url, absolute }
+    Ok(URL { url, absolute })
 }
 
 fn metadata_to_url(config: &Config, metadata: &DynamicContentMetadata) -> URL {
-    url_for_impl(config, &metadata.route, &metadata.stuff)
+    // The title is available to routes as well, e.g. {title|slug}
+    let title = Value::String(metadata.title.clone());
+    url_for_impl(config, &metadata.route, |key| match key {
+        "title" => Some(&title),
+        _ => metadata.stuff.get(key),
+    })
+    .unwrap_or_else(|e| panic!("{}", e))
 }
 
 fn generate_urls(
     config: Res<Config>,
-    query: Query<(Entity, &DynamicContentType, &DynamicContentMetadata)>,
+    query: Query<(Entity, &DynamicContentType, &DynamicContentMetadata), Without<URL>>,
     mut commands: Commands,
 ) {
     for (entity, type_, metadata) in query.iter() {
-        if *type_ == DynamicContentType::BlogpostTagPage {
+        if *type_ == DynamicContentType::BlogpostTagPage
+            || *type_ == DynamicContentType::BlogpostSeriesPage
+            || *type_ == DynamicContentType::BlogpostAuthorPage
+            || *type_ == DynamicContentType::DataPage
+        {
             continue;
         }
         let url = metadata_to_url(&config, metadata);
@@ -424,7 +1236,8 @@ impl tera::Function for UrlFor {
                 .map_err(|_| tera::Error::msg("invalid route")),
             None => Err(tera::Error::msg("missing route")),
         }?;
-        let url = url_for_impl(&self.config, &route, args);
+        let url =
+            url_for_impl(&self.config, &route, |key| args.get(key)).map_err(tera::Error::msg)?;
         Ok(tera::to_value(url.url)?)
     }
 }
@@ -447,84 +1260,100 @@ struct Navbar {
 
 impl Navbar {
     fn for_(&self, url: &str) -> Navbar {
+        Self {
+            entries: Self::mark_active(&self.entries, url),
+        }
+    }
+
+    fn mark_active(entries: &[NavbarEntry], url: &str) -> Vec<NavbarEntry> {
         let is_active = |e: &NavbarEntry| e.url == url || (e.url != "/" && url.starts_with(&e.url));
-        let entries: Vec<_> = self
-            .entries
+        entries
             .iter()
-            .map(|e| {
-                let children = e
-                    .children
-                    .iter()
-                    .map(|e| NavbarEntry {
-                        active: is_active(e),
-                        ..e.clone()
-                    })
-                    .collect();
-                NavbarEntry {
-                    active: is_active(e),
-                    children,
-                    ..e.clone()
-                }
+            .map(|e| NavbarEntry {
+                active: is_active(e),
+                children: Self::mark_active(&e.children, url),
+                ..e.clone()
             })
-            .collect();
-        Self { entries }
+            .collect()
     }
 }
 
+// The group an entry is shown in. Primary entries head their own group,
+// so they're shown in the group that's nested in.
+fn navbar_parent_group(navbar: &NavbarConfig) -> Option<&String> {
+    if navbar.is_primary {
+        navbar.parent_group.as_ref()
+    } else {
+        navbar.group.as_ref()
+    }
+}
+
+fn navbar_group_entries(
+    entries: &[(&URL, &NavbarConfig, String)],
+    group: Option<&String>,
+    visited: &mut Vec<String>,
+) -> Vec<NavbarEntry> {
+    entries
+        .iter()
+        .filter(|(_, navbar, _)| navbar_parent_group(navbar) == group)
+        .sorted_by(|a, b| a.1.index.cmp(&b.1.index))
+        .map(|(url, navbar, title)| {
+            let children = match navbar.group.as_ref() {
+                Some(own) if navbar.is_primary => {
+                    if visited.contains(own) {
+                        panic!("Navbar group {:?} is nested inside itself!", own);
+                    }
+                    visited.push(own.clone());
+                    let children = navbar_group_entries(entries, Some(own), visited);
+                    visited.pop();
+                    children
+                }
+                _ => vec![],
+            };
+            NavbarEntry {
+                url: url.url.to_string(),
+                title: title.clone(),
+                active: false,
+                children,
+            }
+        })
+        .collect()
+}
+
 fn navbar_indexer(query: Query<(&URL, &DynamicContentMetadata)>, mut commands: Commands) {
-    let entries = query
+    let entries: Vec<_> = query
         .iter()
         .filter_map(|(url, metadata)| {
             metadata
                 .navbar
-                .as_ref().map(|navbar| (url, navbar, metadata.title.clone()))
+                .as_ref()
+                .map(|navbar| (url, navbar, metadata.title.clone()))
         })
-        .sorted_by(|(_, n1, _), (_, n2, _)| n1.group.cmp(&n2.group))
-        .chunk_by(|(_, navbar, _)| navbar.group.as_ref())
-        .into_iter()
-        .flat_map(|(key, group)| {
-            let (mut primary, rest): (Vec<_>, Vec<_>) = group.partition(|e| e.1.is_primary);
-            let children = Box::new(
-                rest.into_iter()
-                    .sorted_by(|a, b| a.1.index.cmp(&b.1.index))
-                    .map(|(url, config, title)| {
-                        (
-                            config.index,
-                            NavbarEntry {
-                                url: url.url.to_string(),
-                                title,
-                                active: false,
-                                children: vec![],
-                            },
-                        )
-                    }),
+        .collect();
+    let primaries = entries
+        .iter()
+        .filter(|(_, navbar, _)| navbar.is_primary)
+        .filter_map(|(_, navbar, _)| navbar.group.as_ref())
+        .counts();
+    for (key, count) in &primaries {
+        if *count > 1 {
+            panic!(
+                "Expected navbar group {:?} to have at most one primary element, got {}!",
+                key, count
             );
-            if key.is_none() {
-                return children.collect();
-            }
-            if primary.len() > 1 {
+        }
+    }
+    for (_, navbar, _) in &entries {
+        if let Some(key) = navbar_parent_group(navbar) {
+            if !primaries.contains_key(key) {
                 panic!(
-                    "Expected navbar group {:?} to have at most one primary element, got {}!",
-                    key,
-                    primary.len()
+                    "must have at least one primary element for navbar group {:?}!",
+                    key
                 );
             }
-            let primary = primary.pop().unwrap_or_else(|| panic!("must have at least one primary element for navbar group {:?}!",
-                key));
-            let children = children.map(|(_, child)| child).collect();
-            vec![(
-                primary.1.index,
-                NavbarEntry {
-                    url: primary.0.url.to_string(),
-                    title: primary.2,
-                    active: false,
-                    children,
-                },
-            )]
-        })
-        .sorted_by(|(i1, _), (i2, _)| i1.cmp(i2))
-        .map(|(_, e)| e)
-        .collect();
+        }
+    }
+    let entries = navbar_group_entries(&entries, None, &mut vec![]);
     commands.insert_resource(Navbar { entries });
 }
 
@@ -542,6 +1371,12 @@ struct BlogpostIndexEntry {
     day: String,
     tags: Vec<String>,
     featured: bool,
+    word_count: usize,
+    reading_time: usize,
+    series: Option<String>,
+    series_order: Option<i64>,
+    // Author ids
+    authors: Vec<String>,
 }
 
 // Top level index available for all entries in the blog
@@ -579,6 +1414,28 @@ impl BlogpostIndex {
             .collect()
     }
 
+    fn series(&self) -> Vec<BlogpostSeries> {
+        self.entries
+            .iter()
+            .filter_map(|e| e.series.clone().map(|s| (s, e.clone())))
+            .into_group_map()
+            .into_iter()
+            .sorted_by(|a, b| a.0.cmp(&b.0))
+            .map(|(name, entries)| BlogpostSeries {
+                slug: slugify(&name),
+                name,
+                // Posts without an explicit order go last, in date order
+                entries: entries
+                    .into_iter()
+                    .sorted_by(|a, b| {
+                        let order = |e: &BlogpostIndexEntry| e.series_order.unwrap_or(i64::MAX);
+                        order(a).cmp(&order(b)).then_with(|| a.date.cmp(&b.date))
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
     fn archives(&self) -> Vec<(String, String, Vec<BlogpostIndexEntry>)> {
         let month_names = maplit::hashmap! {
             "01" => "January",
@@ -614,6 +1471,23 @@ impl BlogpostIndex {
     }
 }
 
+// A multi-part series of blogposts, in reading order
+#[derive(Clone, Debug, Serialize)]
+struct BlogpostSeries {
+    name: String,
+    slug: String,
+    entries: Vec<BlogpostIndexEntry>,
+}
+
+// Series a post belongs to, or a series landing page is for
+#[derive(Component, Debug, Serialize)]
+struct InBlogpostSeries {
+    #[serde(flatten)]
+    series: BlogpostSeries,
+    // 1-based position of the post within the series, not set for landing pages
+    position: Option<usize>,
+}
+
 #[derive(Component, Serialize)]
 struct BlogpostTagsAndCounts {
     entries: Vec<(String, usize)>,
@@ -624,14 +1498,43 @@ struct BlogpostArchives {
     entries: Vec<(String, String, Vec<BlogpostIndexEntry>)>,
 }
 
+// Posts around a blogpost, for navigating between posts
+#[derive(Component, Debug)]
+struct BlogpostNeighbours {
+    // Older post
+    prev: Option<BlogpostIndexEntry>,
+    // Newer post
+    next: Option<BlogpostIndexEntry>,
+    // Posts sharing tags with this one, most shared tags first
+    related: Vec<BlogpostIndexEntry>,
+}
+
+fn related_blogposts(
+    entry: &BlogpostIndexEntry,
+    entries: &[BlogpostIndexEntry],
+    limit: usize,
+) -> Vec<BlogpostIndexEntry> {
+    entries
+        .iter()
+        .filter(|e| e.url != entry.url)
+        .map(|e| (e.tags.iter().filter(|t| entry.tags.contains(t)).count(), e))
+        .filter(|(shared, _)| *shared > 0)
+        // Stable sort, so ties stay in reverse date order
+        .sorted_by(|(a, _), (b, _)| b.cmp(a))
+        .take(limit)
+        .map(|(_, e)| e.clone())
+        .collect()
+}
+
 fn blogpost_indexer(
-    query: Query<(&DynamicContentType, &URL, &DynamicContentMetadata)>,
+    config: Res<Config>,
+    query: Query<(Entity, &DynamicContentType, &URL, &DynamicContentMetadata)>,
     mut commands: Commands,
 ) {
     let mut entries: Vec<_> = query
         .iter()
-        .filter(|(type_, _, _)| **type_ == DynamicContentType::Blogpost)
-        .map(|(_, url, metadata)| {
+        .filter(|(_, type_, _, _)| **type_ == DynamicContentType::Blogpost)
+        .map(|(entity, _, url, metadata)| {
             let get_str = |s: &str| metadata.stuff.get(s).unwrap().as_str().unwrap().to_string();
             // This unwrap is safe, we create the slug
             let slug = get_str("slug");
@@ -641,7 +1544,7 @@ fn blogpost_indexer(
             let month = get_str("month");
             let day = get_str("day");
             // Do some basic validation
-            // We need to validate the excerpt
+            // We need to validate the excerpt, it's generated from the contents if not provided
             let excerpt = metadata
                 .stuff
                 .get("excerpt")
@@ -667,7 +1570,21 @@ fn blogpost_indexer(
                 .unwrap_or(&serde_json::Value::Bool(false))
                 .as_bool()
                 .unwrap_or(false);
-            BlogpostIndexEntry {
+            // These are computed when loading the blogpost
+            let get_usize = |s: &str| {
+                metadata
+                    .stuff
+                    .get(s)
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or_default() as usize
+            };
+            let series = metadata
+                .stuff
+                .get("series")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_string());
+            let series_order = metadata.stuff.get("series_order").and_then(|o| o.as_i64());
+            let entry = BlogpostIndexEntry {
                 url: url.url.clone(),
                 slug,
                 title: metadata.title.clone(),
@@ -678,17 +1595,139 @@ fn blogpost_indexer(
                 day,
                 tags,
                 featured,
-            }
+                word_count: get_usize("word_count"),
+                reading_time: get_usize("reading_time"),
+                series,
+                series_order,
+                authors: metadata_authors(metadata),
+            };
+            (entity, entry)
         })
         .collect();
     // Reverse compare
-    entries.sort_by(|a, b| b.date.cmp(&a.date));
-    commands.insert_resource(BlogpostIndex { entries });
+    entries.sort_by(|(_, a), (_, b)| b.date.cmp(&a.date));
+    let (entities, entries): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+    let index = BlogpostIndex { entries };
+    let series = index.series();
+    let entries = &index.entries;
+    for (i, entity) in entities.into_iter().enumerate() {
+        let mut builder = commands.entity(entity);
+        builder.insert(BlogpostNeighbours {
+            prev: entries.get(i + 1).cloned(),
+            next: i.checked_sub(1).map(|i| entries[i].clone()),
+            related: related_blogposts(&entries[i], entries, config.related_posts_limit),
+        });
+        if let Some(name) = entries[i].series.as_ref() {
+            // This unwrap is safe, the series was built from these entries
+            let series = series.iter().find(|s| &s.name == name).unwrap();
+            let position = series
+                .entries
+                .iter()
+                .position(|e| e.url == entries[i].url)
+                .map(|p| p + 1);
+            builder.insert(InBlogpostSeries {
+                series: series.clone(),
+                position,
+            });
+        }
+    }
+    commands.insert_resource(index);
 }
 
 #[derive(Component)]
 struct ExcludeFromSitemap {}
 
+fn robots_txt(config: &RobotsTxtConfig, sitemaps: &[String]) -> String {
+    let mut lines = vec!["User-agent: *".to_string()];
+    if config.disallow_all {
+        lines.push("Disallow: /".to_string());
+    } else {
+        lines.extend(config.allow.iter().map(|a| format!("Allow: {}", a)));
+        lines.extend(config.disallow.iter().map(|d| format!("Disallow: {}", d)));
+        if config.allow.is_empty() && config.disallow.is_empty() {
+            // An empty rule allows everything
+            lines.push("Disallow:".to_string());
+        }
+    }
+    if !sitemaps.is_empty() {
+        lines.push(String::new());
+        lines.extend(sitemaps.iter().map(|s| format!("Sitemap: {}", s)));
+    }
+    lines.iter().map(|l| format!("{}\n", l)).join("")
+}
+
+fn security_txt(config: &SecurityTxtConfig, canonical: &str) -> String {
+    if config.contact.is_empty() {
+        panic!("security.txt needs at least one contact!");
+    }
+    let fields = [
+        ("Contact", &config.contact),
+        ("Encryption", &config.encryption),
+        ("Acknowledgments", &config.acknowledgments),
+        ("Policy", &config.policy),
+        ("Hiring", &config.hiring),
+    ];
+    let mut lines: Vec<_> = fields
+        .iter()
+        .flat_map(|(name, values)| values.iter().map(move |v| format!("{}: {}", name, v)))
+        .collect();
+    lines.push(format!("Expires: {}", config.expires));
+    if let Some(languages) = config.preferred_languages.as_ref() {
+        lines.push(format!("Preferred-Languages: {}", languages));
+    }
+    lines.push(format!("Canonical: {}", canonical));
+    lines.iter().map(|l| format!("{}\n", l)).join("")
+}
+
+fn humans_txt(config: &Config) -> String {
+    let mut contents = "/* TEAM */\n".to_string();
+    for author in config.authors.values() {
+        contents.push_str(&format!("Name: {}\n", author.name));
+        for (site, link) in &author.links {
+            contents.push_str(&format!("{}: {}\n", site, link));
+        }
+        contents.push('\n');
+    }
+    contents.push_str(&format!("/* SITE */\nSite: {}\n", config.sitename));
+    contents
+}
+
+// robots.txt, security.txt and humans.txt, which need to know about the site
+fn well_known_files_generator(
+    config: Res<Config>,
+    query: Query<(&URL, &DynamicContentType)>,
+    mut commands: Commands,
+) {
+    let mut files = vec![];
+    if let Some(robots) = config.robots_txt.as_ref() {
+        let sitemaps: Vec<_> = query
+            .iter()
+            .filter(|(_, type_)| **type_ == DynamicContentType::SitemapPage)
+            .map(|(url, _)| url.absolute.clone())
+            .sorted()
+            .collect();
+        files.push(("/robots.txt", robots_txt(robots, &sitemaps)));
+    }
+    if let Some(security) = config.security_txt.as_ref() {
+        let path = "/.well-known/security.txt";
+        let canonical = format!("{}{}", config.site_url, path);
+        files.push((path, security_txt(security, &canonical)));
+    }
+    if config.humans_txt {
+        files.push(("/humans.txt", humans_txt(&config)));
+    }
+    for (path, contents) in files {
+        commands
+            .spawn_empty()
+            .insert(URL {
+                url: path.to_string(),
+                absolute: format!("{}{}", config.site_url, path),
+            })
+            .insert(WriteContentsToFile { contents })
+            .insert(ExcludeFromSitemap {});
+    }
+}
+
 #[derive(Resource, Serialize)]
 struct Sitemap {
     entries: Vec<String>,
@@ -701,6 +1740,14 @@ fn sitemap_indexer(query: Query<&URL, Without<ExcludeFromSitemap>>, mut commands
     })
 }
 
+// Metadata for a page spawned from a generator template
+// Aliases can only redirect to one page, so they aren't passed on
+fn generated_page_metadata(template: &DynamicContentMetadata) -> DynamicContentMetadata {
+    let mut metadata = template.clone();
+    metadata.aliases.clear();
+    metadata
+}
+
 fn tag_page_generator(
     config: Res<Config>,
     index: Res<BlogpostIndex>,
@@ -724,7 +1771,7 @@ fn tag_page_generator(
         }
         for tag in &tags {
             // TODO: See if we can avoid expensive copies
-            let mut metadata = metadata.clone();
+            let mut metadata = generated_page_metadata(metadata);
             metadata.stuff.insert("tag".to_string(), tag.clone().into());
             let url = metadata_to_url(&config, &metadata);
             sitemap.entries.push(url.url.clone());
@@ -742,86 +1789,1022 @@ fn tag_page_generator(
     sitemap.entries.sort();
 }
 
-// TODO: See if there's a way to avoid copies
-struct BlogpostFetcherFunction {
-    entries: Vec<BlogpostIndexEntry>,
-    skip_featured: bool,
+fn series_page_generator(
+    config: Res<Config>,
+    index: Res<BlogpostIndex>,
+    mut sitemap: ResMut<Sitemap>,
+    query: Query<(
+        &DynamicContentType,
+        &DynamicContentMetadata,
+        &RelativeSourcePath,
+        &DynamicContentContents,
+    )>,
+    mut commands: Commands,
+) {
+    let series = index.series();
+    for (type_, metadata, source_path, contents) in query.iter() {
+        if *type_ != DynamicContentType::BlogpostSeriesPage {
+            continue;
+        }
+        for series in &series {
+            let mut metadata = generated_page_metadata(metadata);
+            metadata
+                .stuff
+                .insert("series".to_string(), series.slug.clone().into());
+            let url = metadata_to_url(&config, &metadata);
+            sitemap.entries.push(url.url.clone());
+            commands
+                .spawn_empty()
+                .insert(source_path.clone())
+                .insert(metadata)
+                .insert(contents.clone())
+                .insert(type_.clone())
+                .insert(url)
+                .insert(InBlogpostSeries {
+                    series: series.clone(),
+                    position: None,
+                });
+        }
+    }
+    sitemap.entries.sort();
 }
 
-impl tera::Function for BlogpostFetcherFunction {
-    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
-        let count = match args.get("count") {
-            Some(val) => tera::from_value::<usize>(val.clone())
-                .map_err(|_| tera::Error::msg("invalid count")),
-            None => Err(tera::Error::msg("invalid count")),
-        }?;
-
-        let tag = match args.get("tag") {
-            Some(val) => tera::from_value::<String>(val.clone())
-                .map_err(|_| tera::Error::msg("invalid tag"))
-                .map(Some),
-            None => Ok(None),
-        }?;
+// Author an author page is for
+#[derive(Component, Debug)]
+struct ForAuthor {
+    author: Author,
+}
 
-        Ok(Value::Array(
-            self.entries
-                .iter()
-                .filter(|e| !self.skip_featured || !e.featured)
-                .filter(|e| match tag.as_ref() {
-                    Some(tag) => e.tags.contains(tag),
-                    None => true,
-                })
-                .take(count)
-                .filter_map(|e| tera::to_value(e).ok())
-                .collect(),
-        ))
+fn author_page_generator(
+    config: Res<Config>,
+    mut sitemap: ResMut<Sitemap>,
+    query: Query<(
+        &DynamicContentType,
+        &DynamicContentMetadata,
+        &RelativeSourcePath,
+        &DynamicContentContents,
+    )>,
+    mut commands: Commands,
+) {
+    for (type_, metadata, source_path, contents) in query.iter() {
+        if *type_ != DynamicContentType::BlogpostAuthorPage {
+            continue;
+        }
+        for (id, author) in &config.authors {
+            let mut metadata = generated_page_metadata(metadata);
+            metadata
+                .stuff
+                .insert("author".to_string(), id.clone().into());
+            let url = metadata_to_url(&config, &metadata);
+            if !metadata.exclude_from_sitemap {
+                sitemap.entries.push(url.url.clone());
+            }
+            commands
+                .spawn_empty()
+                .insert(source_path.clone())
+                .insert(metadata)
+                .insert(contents.clone())
+                .insert(type_.clone())
+                .insert(url)
+                .insert(ForAuthor {
+                    author: author.clone(),
+                });
+        }
     }
+    sitemap.entries.sort();
+}
 
-    fn is_safe(&self) -> bool {
-        false
+// Looks up data by name, with dots to get inside it, e.g. "profile.publications"
+fn lookup_site_data<'a>(data: &'a SiteData, name: &str) -> Option<&'a Value> {
+    let mut parts = name.split('.');
+    let root = data.entries.get(parts.next()?)?;
+    parts.try_fold(root, |value, part| match value {
+        Value::Array(values) => values.get(part.parse::<usize>().ok()?),
+        _ => value.get(part),
+    })
+}
+
+// Records for a data page, from site data or else a built in collection
+fn data_page_records(data: &SiteData, index: &BlogpostIndex, name: &str) -> Option<Value> {
+    match lookup_site_data(data, name) {
+        Some(value) => Some(value.clone()),
+        None if name == "blogposts" => {
+            Some(serde_json::to_value(&index.entries).expect("Couldn't serialize blogpost index!"))
+        }
+        None => None,
     }
 }
 
-fn dynamic_content_generator(
+fn data_page_generator(
     config: Res<Config>,
-    navbar: Res<Navbar>,
-    blogindex: Res<BlogpostIndex>,
-    sitemap: Res<Sitemap>,
-    mut tera: ResMut<TeraResource>,
+    data: Res<SiteData>,
+    index: Res<BlogpostIndex>,
+    mut sitemap: ResMut<Sitemap>,
     query: Query<(
-        Entity,
-        &URL,
         &DynamicContentType,
         &DynamicContentMetadata,
+        &RelativeSourcePath,
         &DynamicContentContents,
     )>,
     mut commands: Commands,
 ) {
-    // TODO: Move some of these to Tera filters
-    let featured_posts = BlogpostFetcherFunction {
-        entries: blogindex.featured(),
-        skip_featured: false,
-    };
-    let recent_posts = BlogpostFetcherFunction {
-        entries: blogindex.recent(),
-        skip_featured: true,
-    };
-    let tagged_posts = BlogpostFetcherFunction {
-        entries: blogindex.recent(),
-        skip_featured: false,
-    };
-    let all_posts = BlogpostFetcherFunction {
-        entries: blogindex.recent(),
-        skip_featured: false,
-    };
-    tera.register_function("blogposts_featured", featured_posts);
-    tera.register_function("blogposts_recent", recent_posts);
+    for (type_, metadata, source_path, contents) in query.iter() {
+        if *type_ != DynamicContentType::DataPage {
+            continue;
+        }
+        let source = source_path.path.to_string_lossy();
+        let name = metadata
+            .stuff
+            .get("data")
+            .and_then(|d| d.as_str())
+            .unwrap_or_else(|| panic!("Data page at {} has no data source!", source));
+        let records = data_page_records(&data, &index, name)
+            .unwrap_or_else(|| panic!("Data page at {} uses unknown data {}!", source, name));
+        let records = records
+            .as_array()
+            .unwrap_or_else(|| panic!("Data {} used by {} is not an array!", name, source));
+        for record in records {
+            let fields = record.as_object().unwrap_or_else(|| {
+                panic!("Data {} used by {} has a non-object record!", name, source)
+            });
+            let mut metadata = generated_page_metadata(metadata);
+            // Record fields fill in the route, and are available to the template
+            for (key, value) in fields {
+                metadata.stuff.insert(key.clone(), value.clone());
+            }
+            metadata.stuff.insert("record".to_string(), record.clone());
+            if let Some(title) = fields.get("title").and_then(|t| t.as_str()) {
+                metadata.title = title.to_string();
+            }
+            let url = metadata_to_url(&config, &metadata);
+            if !metadata.exclude_from_sitemap {
+                sitemap.entries.push(url.url.clone());
+            }
+            commands
+                .spawn_empty()
+                .insert(source_path.clone())
+                .insert(metadata)
+                .insert(contents.clone())
+                .insert(type_.clone())
+                .insert(url);
+        }
+    }
+    sitemap.entries.sort();
+}
+
+// Link to another page, for navigating the content tree
+#[derive(Clone, Debug, Serialize)]
+struct PageLink {
+    url: String,
+    title: String,
+}
+
+// Where a page sits in the content tree
+#[derive(Component, Debug)]
+struct Section {
+    parent: Option<PageLink>,
+    children: Vec<PageLink>,
+    // Starting from the root
+    ancestors: Vec<PageLink>,
+    // Ancestors followed by the page itself
+    breadcrumbs: Vec<PageLink>,
+}
+
+// Each page's parent is the page whose route is named by its `parent` key,
+// otherwise the closest page further up the directory structure.
+fn content_tree_builder(
+    config: Res<Config>,
+    query: Query<(Entity, &URL, &DynamicContentMetadata)>,
+    mut commands: Commands,
+) {
+    // Keyed without the trailing slash, so this works with any trailing slash policy
+    let entities: HashMap<_, _> = query
+        .iter()
+        .map(|(entity, url, _)| (url.url.trim_end_matches('/'), entity))
+        .collect();
+    let mut parents = HashMap::new();
+    for (entity, url, metadata) in query.iter() {
+        let parent = match metadata.stuff.get("parent").and_then(|p| p.as_str()) {
+            Some(route) => {
+                let parent_url = url_for_impl(&config, route, |key| metadata.stuff.get(key))
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .url;
+                let parent = entities
+                    .get(parent_url.trim_end_matches('/'))
+                    .unwrap_or_else(|| {
+                        panic!("Parent {} of {} is not a page!", parent_url, url.url)
+                    });
+                Some(*parent)
+            }
+            // Only directory-like urls are part of the directory structure
+            None if Path::new(&url.url).extension().is_none() => {
+                let trimmed = url.url.trim_end_matches('/');
+                trimmed
+                    .rmatch_indices('/')
+                    .find_map(|(i, _)| entities.get(&trimmed[..i]))
+                    .copied()
+            }
+            None => None,
+        };
+        if let Some(parent) = parent {
+            parents.insert(entity, parent);
+        }
+    }
+    for (&child, &parent) in &parents {
+        let is_cycle = std::iter::successors(Some(parent), |p| parents.get(p).copied())
+            .take(parents.len() + 1)
+            .any(|p| p == child);
+        if is_cycle {
+            let url = &query.get(child).unwrap().1.url;
+            panic!("Page {} is its own ancestor!", url);
+        }
+        commands.entity(parent).add_child(child);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn section_linker(
+    query: Query<(
+        Entity,
+        &URL,
+        &DynamicContentMetadata,
+        Option<&Parent>,
+        Option<&Children>,
+    )>,
+    mut commands: Commands,
+) {
+    let link = |entity: &Entity| {
+        query
+            .get(*entity)
+            .ok()
+            .map(|(_, url, metadata, _, _)| PageLink {
+                url: url.url.clone(),
+                title: metadata.title.clone(),
+            })
+    };
+    for (entity, url, metadata, parent, children) in query.iter() {
+        let mut ancestors: Vec<_> = std::iter::successors(parent.map(|p| p.get()), |e| {
+            query
+                .get(*e)
+                .ok()
+                .and_then(|(_, _, _, p, _)| p.map(|p| p.get()))
+        })
+        .filter_map(|e| link(&e))
+        .collect();
+        ancestors.reverse();
+        let children = children
+            .map(|c| {
+                c.iter()
+                    .filter_map(link)
+                    .sorted_by(|a, b| a.url.cmp(&b.url))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut breadcrumbs = ancestors.clone();
+        breadcrumbs.push(PageLink {
+            url: url.url.clone(),
+            title: metadata.title.clone(),
+        });
+        commands.entity(entity).insert(Section {
+            parent: ancestors.last().cloned(),
+            children,
+            ancestors,
+            breadcrumbs,
+        });
+    }
+}
+
+// Redirects from the last run, shared with the server so it can serve them as 301s
+// Keyed by path without the trailing slash
+#[derive(Resource, Clone, Debug, Default)]
+struct RedirectTable {
+    entries: std::sync::Arc<std::sync::RwLock<BTreeMap<String, String>>>,
+}
+
+impl RedirectTable {
+    fn key(path: &str) -> &str {
+        match path.trim_end_matches('/') {
+            "" => "/",
+            key => key,
+        }
+    }
+
+    fn get(&self, path: &str) -> Option<String> {
+        let entries = self.entries.read().expect("Redirect table is poisoned!");
+        entries.get(Self::key(path)).cloned()
+    }
+
+    fn keyed(redirects: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        redirects
+            .iter()
+            .map(|(from, to)| (Self::key(from).to_string(), to.clone()))
+            .collect()
+    }
+
+    fn replace(&self, redirects: &BTreeMap<String, String>) {
+        let mut entries = self.entries.write().expect("Redirect table is poisoned!");
+        *entries = Self::keyed(redirects);
+    }
+}
+
+// Redirects found by this run, only served once its output is in place
+#[derive(Resource, Debug)]
+struct GeneratedRedirects {
+    entries: BTreeMap<String, String>,
+}
+
+fn redirect_table_updater(generated: Res<GeneratedRedirects>, table: Res<RedirectTable>) {
+    table.replace(&generated.entries);
+}
+
+// A stub page at an old URL, pointing at where the content lives now
+#[derive(Component, Debug)]
+struct Redirect {
+    to: String,
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn redirect_stub_page(config: &Config, to: &str) -> String {
+    let canonical = if to.starts_with('/') {
+        format!("{}{}", config.site_url, to)
+    } else {
+        to.to_string()
+    };
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html>\n<head>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<title>Redirecting&hellip;</title>\n",
+            "<link rel=\"canonical\" href=\"{canonical}\">\n",
+            "<meta name=\"robots\" content=\"noindex\">\n",
+            "<meta http-equiv=\"refresh\" content=\"0; url={to}\">\n",
+            "</head>\n<body>\n",
+            "<a href=\"{to}\">Click here if you are not redirected.</a>\n",
+            "</body>\n</html>\n"
+        ),
+        canonical = escape_attribute(&canonical),
+        to = escape_attribute(to),
+    )
+}
+
+fn redirect_generator(
+    config: Res<Config>,
+    query: Query<(&URL, &DynamicContentMetadata, &RelativeSourcePath)>,
+    mut commands: Commands,
+) {
+    let mut redirects = config.redirects.clone();
+    for (url, metadata, source_path) in query.iter() {
+        for alias in &metadata.aliases {
+            if let Some(other) = redirects.insert(alias.clone(), url.url.clone()) {
+                panic!(
+                    "Alias {} of {} already redirects to {}!",
+                    alias,
+                    source_path.path.to_string_lossy(),
+                    other
+                );
+            }
+        }
+    }
+    for (from, to) in &redirects {
+        if !from.starts_with('/') {
+            panic!("Redirect from {} must be an absolute path!", from);
+        }
+        commands
+            .spawn_empty()
+            .insert(URL {
+                url: from.clone(),
+                absolute: format!("{}{}", config.site_url, from),
+            })
+            .insert(WriteContentsToFile {
+                contents: redirect_stub_page(&config, to),
+            })
+            .insert(Redirect { to: to.clone() })
+            .insert(ExcludeFromSitemap {});
+    }
+    let mut files = vec![];
+    if config.redirect_files.netlify {
+        let contents = redirects
+            .iter()
+            .map(|(from, to)| format!("{} {} 301\n", from, to))
+            .join("");
+        files.push(("/_redirects", contents));
+    }
+    if config.redirect_files.nginx {
+        // Exact locations match literally, so both trailing slash forms are needed
+        let contents = redirects
+            .iter()
+            .flat_map(|(from, to)| {
+                let trimmed = from.trim_end_matches('/');
+                let froms = if trimmed.is_empty() {
+                    vec!["/".to_string()]
+                } else {
+                    vec![trimmed.to_string(), format!("{}/", trimmed)]
+                };
+                froms
+                    .into_iter()
+                    .map(move |from| format!("location = {} {{ return 301 {}; }}\n", from, to))
+            })
+            .join("");
+        files.push(("/redirects.nginx.conf", contents));
+    }
+    for (path, contents) in files {
+        commands
+            .spawn_empty()
+            .insert(URL {
+                url: path.to_string(),
+                absolute: format!("{}{}", config.site_url, path),
+            })
+            .insert(RelativeOutputPath {
+                path: PathBuf::from(path),
+            })
+            .insert(WriteContentsToFile { contents })
+            .insert(ExcludeFromSitemap {});
+    }
+    commands.insert_resource(GeneratedRedirects { entries: redirects });
+}
+
+// Serves redirects as 301s rather than the stub pages
+async fn redirect_middleware(
+    axum::extract::State(table): axum::extract::State<RedirectTable>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    match table.get(request.uri().path()) {
+        Some(to) => (
+            axum::http::StatusCode::MOVED_PERMANENTLY,
+            [(axum::http::header::LOCATION, to)],
+        )
+            .into_response(),
+        None => next.run(request).await,
+    }
+}
+
+// An SVG to rasterize into a PNG preview image
+#[derive(Component, Debug)]
+struct SocialImage {
+    svg: String,
+}
+
+fn social_image_generator(
+    config: Res<Config>,
+    tera: Res<TeraResource>,
+    mut query: Query<(&URL, &mut DynamicContentMetadata)>,
+    mut commands: Commands,
+) {
+    let social = match config.social_images.as_ref() {
+        Some(social) => social,
+        None => return,
+    };
+    for (url, mut metadata) in query.iter_mut() {
+        // Only pages get a preview, not feeds and the like
+        if metadata.og_image.is_some() || Path::new(&url.url).extension().is_some() {
+            continue;
+        }
+        let name = match url.url.trim_matches('/') {
+            "" => "index",
+            name => name,
+        };
+        let image_url = format!("/{}/{}.png", social.output_dir.trim_matches('/'), name);
+        // The template is SVG, which Tera doesn't escape for us
+        let mut context = tera::Context::new();
+        context.insert("title", &escape_attribute(&metadata.title));
+        context.insert("sitename", &escape_attribute(&config.sitename));
+        context.insert("date", &iso_date(&metadata, "date"));
+        context.insert("background", &social.background);
+        let svg = tera
+            .render(&social.template, &context)
+            .unwrap_or_else(|e| panic!("Error generating social image for {}: {}", url.url, e));
+        metadata.og_image = Some(image_url.clone());
+        commands
+            .spawn_empty()
+            .insert(URL {
+                absolute: format!("{}{}", config.site_url, image_url),
+                url: image_url,
+            })
+            .insert(SocialImage { svg })
+            .insert(ExcludeFromSitemap {});
+    }
+}
+
+// TODO: See if there's a way to avoid copies
+struct BlogpostFetcherFunction {
+    entries: Vec<BlogpostIndexEntry>,
+    skip_featured: bool,
+}
+
+impl tera::Function for BlogpostFetcherFunction {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let count = match args.get("count") {
+            Some(val) => tera::from_value::<usize>(val.clone())
+                .map_err(|_| tera::Error::msg("invalid count")),
+            None => Err(tera::Error::msg("invalid count")),
+        }?;
+
+        let tag = match args.get("tag") {
+            Some(val) => tera::from_value::<String>(val.clone())
+                .map_err(|_| tera::Error::msg("invalid tag"))
+                .map(Some),
+            None => Ok(None),
+        }?;
+
+        let author = match args.get("author") {
+            Some(val) => tera::from_value::<String>(val.clone())
+                .map_err(|_| tera::Error::msg("invalid author"))
+                .map(Some),
+            None => Ok(None),
+        }?;
+
+        Ok(Value::Array(
+            self.entries
+                .iter()
+                .filter(|e| !self.skip_featured || !e.featured)
+                .filter(|e| match tag.as_ref() {
+                    Some(tag) => e.tags.contains(tag),
+                    None => true,
+                })
+                .filter(|e| match author.as_ref() {
+                    Some(author) => e.authors.contains(author),
+                    None => true,
+                })
+                .take(count)
+                .filter_map(|e| tera::to_value(e).ok())
+                .collect(),
+        ))
+    }
+
+    fn is_safe(&self) -> bool {
+        false
+    }
+}
+
+// Transliterates to ASCII where possible (e.g. é -> e), keeping letters from
+// scripts without a transliteration as they are.
+fn slugify(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| {
+            let transliterated = deunicode::deunicode_char(c)
+                .filter(|t| !c.is_ascii() && t.chars().any(|c| c.is_ascii_alphanumeric()));
+            match transliterated {
+                Some(t) => t.chars().collect::<Vec<_>>(),
+                None => vec![c],
+            }
+        })
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().collect::<String>()
+            } else {
+                "-".to_string()
+            }
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .join("-")
+}
+
+// A heading in a markdown page, with any subheadings nested inside
+#[derive(Clone, Debug, Serialize)]
+struct TocEntry {
+    level: usize,
+    id: String,
+    title: String,
+    children: Vec<TocEntry>,
+}
+
+fn insert_toc_entry(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+    match entries.last_mut() {
+        Some(last) if last.level < entry.level => insert_toc_entry(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
+}
+
+// Renders markdown to HTML, giving every heading a unique id.
+// Returns the HTML and the table of contents built from the headings.
+fn render_markdown(config: &MarkdownConfig, contents: &str) -> (String, Vec<TocEntry>) {
+    use pulldown_cmark::{Event, Tag, TagEnd};
+    let mut events: Vec<_> =
+        pulldown_cmark::Parser::new_ext(contents, pulldown_cmark::Options::empty()).collect();
+    let mut toc = vec![];
+    let mut used_ids = HashSet::new();
+    let mut i = 0;
+    while i < events.len() {
+        let level = match &events[i] {
+            Event::Start(Tag::Heading { level, .. }) => *level,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let title: String = events[i + 1..]
+            .iter()
+            .take_while(|e| !matches!(e, Event::End(TagEnd::Heading(_))))
+            .filter_map(|e| match e {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let slug = match slugify(&title) {
+            s if s.is_empty() => "section".to_string(),
+            s => s,
+        };
+        let id = (0..)
+            .map(|n| match n {
+                0 => slug.clone(),
+                n => format!("{}-{}", slug, n),
+            })
+            .find(|id| !used_ids.contains(id))
+            .unwrap();
+        used_ids.insert(id.clone());
+        if let Event::Start(Tag::Heading { id: heading_id, .. }) = &mut events[i] {
+            *heading_id = Some(id.clone().into());
+        }
+        if config.heading_anchors {
+            let anchor = format!(
+                "<a class=\"heading-anchor\" href=\"#{}\" aria-hidden=\"true\">#</a> ",
+                id
+            );
+            events.insert(i + 1, Event::InlineHtml(anchor.into()));
+        }
+        insert_toc_entry(
+            &mut toc,
+            TocEntry {
+                level: level as usize,
+                id,
+                title,
+                children: vec![],
+            },
+        );
+        i += 1;
+    }
+    let mut html_output: String = String::with_capacity(contents.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
+    (html_output, toc)
+}
+
+// A shortcode invoked from markdown, e.g. {{< youtube id="..." >}}
+// Paired shortcodes ({{< note >}}...{{< /note >}}) get their contents as `body`
+#[derive(Debug)]
+struct Shortcode {
+    name: String,
+    args: HashMap<String, Value>,
+    body: Option<String>,
+    // Location of the whole invocation in the source
+    range: std::ops::Range<usize>,
+}
+
+// Where the code spans and blocks are in some markdown
+fn markdown_code_ranges(contents: &str) -> Vec<std::ops::Range<usize>> {
+    use pulldown_cmark::{Event, Tag};
+    pulldown_cmark::Parser::new_ext(contents, pulldown_cmark::Options::empty())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+// Finds the next {{< ... >}} tag at or after `from`, returning its range and inner text
+// Tags inside code are left alone, so they can be shown as examples
+fn find_shortcode_tag<'a>(
+    contents: &'a str,
+    mut from: usize,
+    code: &[std::ops::Range<usize>],
+) -> Option<(std::ops::Range<usize>, &'a str)> {
+    loop {
+        let start = contents[from..].find("{{<")? + from;
+        match code.iter().find(|range| range.contains(&start)) {
+            Some(range) => from = range.end,
+            None => {
+                let end = contents[start..].find(">}}")? + start + 3;
+                return Some((start..end, contents[start + 3..end - 3].trim()));
+            }
+        }
+    }
+}
+
+// Arguments are key=value pairs, values are JSON literals or strings
+fn parse_shortcode_args(name: &str, args: &str) -> HashMap<String, Value> {
+    let mut parsed = HashMap::new();
+    let mut rest = args.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .unwrap_or_else(|| panic!("Invalid argument for shortcode {}: {}", name, rest));
+        let value = value.trim_start();
+        let (parsed_value, len) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or_else(|| {
+                    panic!("Unterminated string for shortcode {}: {}", name, rest)
+                });
+                (Value::String(quoted[..end].to_string()), end + 2)
+            }
+            None => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                let raw = &value[..end];
+                let parsed_value = serde_json::from_str(raw).unwrap_or_else(|_| raw.into());
+                (parsed_value, end)
+            }
+        };
+        parsed.insert(key.trim().to_string(), parsed_value);
+        rest = value[len..].trim_start();
+    }
+    parsed
+}
+
+fn parse_shortcodes(contents: &str) -> Vec<Shortcode> {
+    let code = markdown_code_ranges(contents);
+    let mut shortcodes = vec![];
+    let mut from = 0;
+    while let Some((range, inner)) = find_shortcode_tag(contents, from, &code) {
+        let (name, args) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+        if name.starts_with('/') {
+            panic!("Closing shortcode {} has no opening shortcode!", name);
+        }
+        let mut shortcode = Shortcode {
+            name: name.to_string(),
+            args: parse_shortcode_args(name, args),
+            body: None,
+            range: range.clone(),
+        };
+        // Paired if there's a closing tag before the next use of the same shortcode
+        let closing =
+            std::iter::successors(find_shortcode_tag(contents, range.end, &code), |(r, _)| {
+                find_shortcode_tag(contents, r.end, &code)
+            })
+            .take_while(|(_, inner)| inner.split_whitespace().next() != Some(name))
+            .find(|(_, inner)| inner.strip_prefix('/').map(str::trim) == Some(name));
+        if let Some((closing, _)) = closing {
+            shortcode.body = Some(contents[range.end..closing.start].to_string());
+            shortcode.range = range.start..closing.end;
+        }
+        from = shortcode.range.end;
+        shortcodes.push(shortcode);
+    }
+    shortcodes
+}
+
+fn replace_shortcodes(
+    contents: &str,
+    shortcodes: &[Shortcode],
+    replacement: impl Fn(usize) -> String,
+) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut last = 0;
+    for (i, shortcode) in shortcodes.iter().enumerate() {
+        output.push_str(&contents[last..shortcode.range.start]);
+        output.push_str(&replacement(i));
+        last = shortcode.range.end;
+    }
+    output.push_str(&contents[last..]);
+    output
+}
+
+// Stands in for a shortcode while the markdown is rendered, so markdown doesn't mangle its output
+fn shortcode_placeholder(i: usize) -> String {
+    format!("SUJISHORTCODE{}END", i)
+}
+
+fn fill_shortcode_placeholders(html: &str, rendered: &[String]) -> String {
+    rendered
+        .iter()
+        .enumerate()
+        .fold(html.to_string(), |html, (i, rendered)| {
+            let placeholder = shortcode_placeholder(i);
+            // Shortcodes on their own line shouldn't be wrapped in a paragraph
+            html.replace(&format!("<p>{}</p>", placeholder), rendered)
+                .replace(&placeholder, rendered)
+        })
+}
+
+// Code can show template syntax (like shortcodes) as is, so keep Tera away from it
+fn raw_code_elements(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<code") {
+        let end = match rest[start..].find("</code>") {
+            Some(end) => start + end + "</code>".len(),
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(&format!("{{% raw %}}{}{{% endraw %}}", &rest[start..end]));
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn render_shortcode(tera: &Tera, shortcode: &Shortcode, title: &str, url: &str) -> String {
+    let mut context = tera::Context::new();
+    shortcode
+        .args
+        .iter()
+        .for_each(|(k, v)| context.insert(k, v));
+    if let Some(body) = shortcode.body.as_ref() {
+        context.insert("body", body);
+    }
+    context.insert("page_title", title);
+    context.insert("page_url", url);
+    tera.render(&format!("shortcodes/{}.html", shortcode.name), &context)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Error rendering shortcode {} for {}: {:?}",
+                shortcode.name, url, e
+            )
+        })
+}
+
+fn absolute_url(config: &Config, url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{}", config.site_url, url)
+    } else {
+        url.to_string()
+    }
+}
+
+// Turns one of our YYYY/MM/DD dates into an ISO 8601 one
+fn iso_date(metadata: &DynamicContentMetadata, key: &str) -> Option<String> {
+    metadata
+        .stuff
+        .get(key)
+        .and_then(|d| d.as_str())
+        .map(|d| d.replace('/', "-"))
+}
+
+// OpenGraph, Twitter card, canonical URL and JSON-LD metadata for a page
+fn insert_seo_metadata(
+    context: &mut tera::Context,
+    config: &Config,
+    url: &URL,
+    metadata: &DynamicContentMetadata,
+    authors: &[&Author],
+) {
+    let title = if metadata.og_title.is_empty() {
+        &metadata.title
+    } else {
+        &metadata.og_title
+    };
+    let og_type = if metadata.og_type.is_empty() {
+        "website"
+    } else {
+        metadata.og_type.as_str()
+    };
+    let image = metadata
+        .og_image
+        .as_ref()
+        .or(config.seo.default_image.as_ref())
+        .map(|i| absolute_url(config, i));
+    let canonical_url = metadata
+        .canonical_url
+        .as_ref()
+        .map(|c| absolute_url(config, c))
+        .unwrap_or_else(|| url.absolute.clone());
+    let published_time = iso_date(metadata, "date");
+    // Falls back to when it was published
+    let modified_time = iso_date(metadata, "updated").or_else(|| published_time.clone());
+    let tags: Vec<_> = metadata
+        .stuff
+        .get("tags")
+        .and_then(|t| t.as_array())
+        .map(|t| t.iter().filter_map(|t| t.as_str()).collect())
+        .unwrap_or_default();
+    let twitter_card = metadata.twitter_card.clone().unwrap_or_else(|| {
+        match image {
+            Some(_) => "summary_large_image",
+            None => "summary",
+        }
+        .to_string()
+    });
+    context.insert("canonical_url", &canonical_url);
+    context.insert("og_url", &url.absolute);
+    context.insert("og_type", og_type);
+    context.insert("og_title", title);
+    context.insert("og_description", &metadata.og_description);
+    context.insert("og_image", &image);
+    context.insert("og_site_name", &config.sitename);
+    context.insert("og_published_time", &published_time);
+    context.insert("og_modified_time", &modified_time);
+    context.insert("og_tags", &tags);
+    context.insert("twitter_card", &twitter_card);
+    context.insert("twitter_site", &config.seo.twitter_site);
+    context.insert("twitter_title", title);
+    context.insert("twitter_description", &metadata.og_description);
+    context.insert("twitter_image", &image);
+
+    let mut json_ld = serde_json::Map::new();
+    json_ld.insert("@context".to_string(), "https://schema.org".into());
+    if og_type == "article" {
+        let authors: Vec<_> = authors
+            .iter()
+            .map(|a| serde_json::json!({"@type": "Person", "name": a.name}))
+            .collect();
+        json_ld.insert("@type".to_string(), "BlogPosting".into());
+        json_ld.insert("headline".to_string(), title.clone().into());
+        json_ld.insert("mainEntityOfPage".to_string(), canonical_url.into());
+        json_ld.insert("datePublished".to_string(), published_time.into());
+        json_ld.insert("dateModified".to_string(), modified_time.into());
+        json_ld.insert("keywords".to_string(), tags.join(", ").into());
+        json_ld.insert("author".to_string(), authors.into());
+        json_ld.insert(
+            "publisher".to_string(),
+            serde_json::json!({"@type": "Organization", "name": config.sitename}),
+        );
+    } else if url.url == "/" {
+        json_ld.insert("@type".to_string(), "WebSite".into());
+        json_ld.insert("name".to_string(), config.sitename.clone().into());
+    } else {
+        json_ld.insert("@type".to_string(), "WebPage".into());
+        json_ld.insert("name".to_string(), title.clone().into());
+    }
+    json_ld.insert("url".to_string(), url.absolute.clone().into());
+    if !metadata.og_description.is_empty() {
+        json_ld.insert(
+            "description".to_string(),
+            metadata.og_description.clone().into(),
+        );
+    }
+    if let Some(image) = image {
+        json_ld.insert("image".to_string(), image.into());
+    }
+    let json_ld = Value::Object(json_ld);
+    // Serialized to go inside a script tag, so it can't be allowed to close it
+    let json_ld_script = json_ld.to_string().replace("</", "<\\/");
+    context.insert("json_ld", &json_ld);
+    context.insert("json_ld_script", &json_ld_script);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn dynamic_content_generator(
+    config: Res<Config>,
+    navbar: Res<Navbar>,
+    blogindex: Res<BlogpostIndex>,
+    sitemap: Res<Sitemap>,
+    assets: Res<AssetManifest>,
+    data: Res<SiteData>,
+    mut tera: ResMut<TeraResource>,
+    query: Query<(
+        Entity,
+        &URL,
+        &DynamicContentType,
+        &DynamicContentMetadata,
+        &DynamicContentContents,
+        Option<&BlogpostNeighbours>,
+        Option<&InBlogpostSeries>,
+        Option<&ForAuthor>,
+        Option<&Section>,
+    )>,
+    mut commands: Commands,
+) {
+    // TODO: Move some of these to Tera filters
+    let featured_posts = BlogpostFetcherFunction {
+        entries: blogindex.featured(),
+        skip_featured: false,
+    };
+    let recent_posts = BlogpostFetcherFunction {
+        entries: blogindex.recent(),
+        skip_featured: true,
+    };
+    let tagged_posts = BlogpostFetcherFunction {
+        entries: blogindex.recent(),
+        skip_featured: false,
+    };
+    let all_posts = BlogpostFetcherFunction {
+        entries: blogindex.recent(),
+        skip_featured: false,
+    };
+    tera.register_function("blogposts_featured", featured_posts);
+    tera.register_function("blogposts_recent", recent_posts);
     tera.register_function("blogposts_tagged", tagged_posts);
     tera.register_function("blogposts_all", all_posts);
     let url_for = UrlFor {
         config: config.clone(),
     };
     tera.register_function("url_for", url_for);
+    let markdown_config = config.markdown.clone();
+    tera.register_filter(
+        "markdown",
+        move |value: &Value, _: &HashMap<String, Value>| {
+            let markdown = tera::from_value::<String>(value.clone())
+                .map_err(|_| tera::Error::msg("markdown filter expects a string"))?;
+            let (html, _) = render_markdown(&markdown_config, &markdown);
+            Ok(tera::to_value(html)?)
+        },
+    );
+    tera.register_function(
+        "asset_url",
+        AssetUrl {
+            entries: assets.entries.clone(),
+        },
+    );
     let tags_and_counts = tera::to_value(BlogpostTagsAndCounts {
         entries: blogindex.tags_and_counts(),
     })
@@ -830,6 +2813,9 @@ fn dynamic_content_generator(
         entries: blogindex.archives(),
     })
     .expect("Couldn't serialize blogpost archives");
+    // The effective config, after applying the profile and overrides
+    let mut env = tera::to_value(&*config).expect("Couldn't serialize config");
+    env["name"] = config.profile.clone().into();
     let sitemap = tera::to_value(Sitemap {
         entries: sitemap.entries.clone(),
     })
@@ -848,24 +2834,63 @@ fn dynamic_content_generator(
         )
     });
     // TODO: Figure out parallelization
-    for (entity, url, type_, metadata, contents) in query.iter() {
+    for (entity, url, type_, metadata, contents, neighbours, series, author, section) in
+        query.iter()
+    {
         let mut context = tera::Context::new();
         context.insert("sitename", &config.sitename);
+        context.insert("env", &env);
+        context.insert("data", &data.entries);
         context.insert("title", &metadata.title);
         metadata
             .stuff
             .iter()
             .for_each(|(k, v)| context.insert(k, v));
         context.insert("navbar", &navbar.for_(&url.url));
+        if let Some(neighbours) = neighbours {
+            context.insert("prev", &neighbours.prev);
+            context.insert("next", &neighbours.next);
+            context.insert("related", &neighbours.related);
+        }
+        if let Some(series) = series {
+            context.insert("series", series);
+        }
+        let authors: Vec<_> = metadata_authors(metadata)
+            .iter()
+            .filter_map(|id| config.authors.get(id))
+            .collect();
+        if !authors.is_empty() {
+            context.insert("authors", &authors);
+        }
+        if let Some(author) = author {
+            context.insert("author", &author.author);
+        }
+        if let Some(section) = section {
+            context.insert("parent", &section.parent);
+            context.insert("children", &section.children);
+            context.insert("ancestors", &section.ancestors);
+            context.insert("breadcrumbs", &section.breadcrumbs);
+        }
         let html_output = if metadata.markdown {
-            let parser = pulldown_cmark::Parser::new_ext(
-                &contents.contents,
-                pulldown_cmark::Options::empty(),
+            let shortcodes = parse_shortcodes(&contents.contents);
+            let markdown =
+                replace_shortcodes(&contents.contents, &shortcodes, shortcode_placeholder);
+            let (html_output, toc) = render_markdown(&config.markdown, &markdown);
+            let rendered: Vec<_> = shortcodes
+                .iter()
+                .map(|shortcode| render_shortcode(&tera, shortcode, &metadata.title, &url.url))
+                .collect();
+            context.insert(
+                "content",
+                &fill_shortcode_placeholders(&html_output, &rendered),
             );
-            let mut html_output: String = String::with_capacity(contents.contents.len() * 3 / 2);
-            pulldown_cmark::html::push_html(&mut html_output, parser);
-            context.insert("content", &html_output);
-            html_output
+            context.insert("toc", &toc);
+            // Blogposts get spliced into their template, so keep Tera away from shortcode output
+            let raw: Vec<_> = rendered
+                .iter()
+                .map(|r| format!("{{% raw %}}{}{{% endraw %}}", r))
+                .collect();
+            fill_shortcode_placeholders(&raw_code_elements(&html_output), &raw)
         } else {
             String::new()
         };
@@ -873,14 +2898,7 @@ fn dynamic_content_generator(
         context.insert("blog_archives", &blog_archives);
         context.insert("sitemap", &sitemap);
         context.insert("url_for_this", &url.url);
-        context.insert("og_url", &url.absolute);
-        context.insert("og_type", &metadata.og_type);
-        if !metadata.og_title.is_empty() {
-            context.insert("og_title", &metadata.og_title);
-        } else {
-            context.insert("og_title", &metadata.title);
-        }
-        context.insert("og_description", &metadata.og_description);
+        insert_seo_metadata(&mut context, &config, url, metadata, &authors);
         // TODO: Better error messages
         let contents = if let Some(template_name) = metadata.template.clone() {
             match type_ {
@@ -942,6 +2960,46 @@ fn map_urls_to_relative_paths(
     }
 }
 
+// Fails the build if two entities end up at the same URL or output path
+#[allow(clippy::type_complexity)]
+fn permalink_conflict_checker(
+    query: Query<(
+        &URL,
+        &RelativeOutputPath,
+        Option<&RelativeSourcePath>,
+        Option<&AssetSource>,
+        Option<&Redirect>,
+    )>,
+) {
+    let mut urls = HashMap::new();
+    let mut paths = HashMap::new();
+    for (url, path, source_path, asset, redirect) in query.iter() {
+        // Pages generated from the same source are told apart by their URL
+        let source = match (source_path, asset, redirect) {
+            (Some(source), _, _) => format!("{} ({})", source.path.to_string_lossy(), url.url),
+            (None, Some(asset), _) => format!("asset bundle {}", asset.name),
+            (None, None, Some(redirect)) => format!("redirect to {}", redirect.to),
+            (None, None, None) => url.url.clone(),
+        };
+        // Static content URLs don't have a leading slash
+        if let Some(other) = urls.insert(url.url.trim_start_matches('/'), source.clone()) {
+            panic!(
+                "URL {} is generated by both {} and {}!",
+                url.url, other, source
+            );
+        }
+        let output = path.path.strip_prefix("/").unwrap_or(path.path.as_path());
+        if let Some(other) = paths.insert(output, source.clone()) {
+            panic!(
+                "Output path {} is written by both {} and {}!",
+                output.to_string_lossy(),
+                other,
+                source
+            );
+        }
+    }
+}
+
 #[derive(Component, Debug)]
 struct AbsoluteOutputPath {
     path: PathBuf,
@@ -995,21 +3053,252 @@ fn static_file_copier(
         &CopySourceToOutput,
     )>,
 ) {
-    // TODO: Look at batch sizes here
-    query.par_iter().for_each(|(from, to, _)| {
-        std::fs::copy(from.path.as_path(), to.path.as_path()).unwrap_or_else(|_| {
-            panic!(
-                "Unable to copy {} to {}",
-                from.path.as_path().to_string_lossy(),
-                to.path.as_path().to_string_lossy()
-            )
+    // TODO: Look at batch sizes here
+    query.par_iter().for_each(|(from, to, _)| {
+        std::fs::copy(from.path.as_path(), to.path.as_path()).unwrap_or_else(|_| {
+            panic!(
+                "Unable to copy {} to {}",
+                from.path.as_path().to_string_lossy(),
+                to.path.as_path().to_string_lossy()
+            )
+        });
+    });
+}
+
+#[derive(Component)]
+struct WriteContentsToFile {
+    contents: String,
+}
+
+// Elements whose contents are whitespace sensitive (or not HTML at all)
+const HTML_RAW_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+fn is_unquotable_attribute_value(value: &str) -> bool {
+    !value.is_empty()
+        && !value.ends_with('/')
+        && !value
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || "\"'=<>`".contains(c))
+}
+
+// Minifies a single tag starting at the beginning of input.
+// Returns the minified tag, the lowercased tag name and how much input was consumed.
+fn minify_html_tag(input: &str) -> (String, String, usize) {
+    let mut quote = None;
+    let mut end = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => {
+                end = Some(i);
+                break;
+            }
+            None => {}
+        }
+    }
+    let end = match end {
+        Some(end) => end,
+        // Not a well formed tag, leave it alone
+        None => return (input.to_string(), String::new(), input.len()),
+    };
+    let inner = &input[1..end];
+    if inner.starts_with('!') || inner.starts_with('?') {
+        let tag = format!("<{}>", inner.split_ascii_whitespace().join(" "));
+        return (tag, String::new(), end + 1);
+    }
+    let name_end = inner
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| c.is_ascii_whitespace() || *c == '/')
+        .map(|(i, _)| i)
+        .unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    let mut tag = format!("<{}", name);
+    let mut rest = inner[name_end..].trim();
+    let self_closing = rest.ends_with('/');
+    if self_closing {
+        rest = rest[..rest.len() - 1].trim_end();
+    }
+    let mut last_unquoted = false;
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        tag.push(' ');
+        tag.push_str(&rest[..name_end]);
+        last_unquoted = false;
+        rest = rest[name_end..].trim_start();
+        if let Some(value) = rest.strip_prefix('=') {
+            let value = value.trim_start();
+            let (raw, len) = match value.chars().next() {
+                Some(q) if q == '"' || q == '\'' => match value[1..].find(q) {
+                    Some(i) => (&value[1..i + 1], i + 2),
+                    None => (&value[1..], value.len()),
+                },
+                _ => {
+                    let len = value
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(value.len());
+                    (&value[..len], len)
+                }
+            };
+            if is_unquotable_attribute_value(raw) {
+                tag.push('=');
+                tag.push_str(raw);
+                last_unquoted = true;
+            } else if raw.contains('"') && !raw.contains('\'') {
+                tag.push_str(&format!("='{}'", raw));
+            } else if raw.contains('"') {
+                // Neither quote works as is, so escape the double quotes
+                tag.push_str(&format!("=\"{}\"", raw.replace('"', "&quot;")));
+            } else {
+                tag.push_str(&format!("=\"{}\"", raw));
+            }
+            rest = value[len..].trim_start();
+        }
+    }
+    if self_closing {
+        if last_unquoted {
+            tag.push(' ');
+        }
+        tag.push('/');
+    }
+    tag.push('>');
+    (tag, name.to_ascii_lowercase(), end + 1)
+}
+
+// Finds where a raw element is closed, ignoring case
+fn find_closing_tag(html: &str, name: &str) -> Option<usize> {
+    html.match_indices("</").map(|(i, _)| i).find(|i| {
+        html.as_bytes()
+            .get(i + 2..i + 2 + name.len())
+            .is_some_and(|n| n.eq_ignore_ascii_case(name.as_bytes()))
+    })
+}
+
+// Collapses whitespace and removes comments, leaving raw elements untouched
+fn minify_html(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map(|i| i + 3).unwrap_or(rest.len());
+            // Conditional comments still have meaning to some browsers
+            if rest.starts_with("<!--[if") {
+                output.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || "/!?".contains(c))
+        {
+            let (tag, name, len) = minify_html_tag(rest);
+            output.push_str(&tag);
+            rest = &rest[len..];
+            if HTML_RAW_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+                let end = find_closing_tag(rest, &name).unwrap_or(rest.len());
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+        } else {
+            // The first char can be a stray '<', which isn't the start of the next tag
+            let first = rest.chars().next().map(char::len_utf8).unwrap_or(0);
+            let end = rest[first..]
+                .find('<')
+                .map(|i| i + first)
+                .unwrap_or(rest.len());
+            for c in rest[..end].chars() {
+                if !c.is_ascii_whitespace() {
+                    output.push(c);
+                } else if !output.is_empty() && !output.ends_with(' ') {
+                    output.push(' ');
+                }
+            }
+            rest = &rest[end..];
+        }
+    }
+    output.trim_end().to_string()
+}
+
+fn html_minifier(
+    config: Res<Config>,
+    mut query: Query<(
+        &RelativeOutputPath,
+        &DynamicContentMetadata,
+        &mut WriteContentsToFile,
+    )>,
+) {
+    if !config.minify_html {
+        return;
+    }
+    query
+        .par_iter_mut()
+        .for_each(|(path, metadata, mut contents)| {
+            let is_html = path
+                .path
+                .extension()
+                .map(|e| e == "html" || e == "htm")
+                .unwrap_or(false);
+            if is_html && !metadata.skip_minify_html {
+                contents.contents = minify_html(&contents.contents);
+            }
         });
-    });
 }
 
-#[derive(Component)]
-struct WriteContentsToFile {
-    contents: String,
+type SocialImageFonts = std::sync::Arc<resvg::usvg::fontdb::Database>;
+
+// System fonts plus the configured ones, if social images are enabled
+fn social_image_fonts(config: &Config) -> Option<SocialImageFonts> {
+    let social = config.social_images.as_ref()?;
+    let mut fonts = resvg::usvg::fontdb::Database::new();
+    fonts.load_system_fonts();
+    for font in &social.fonts {
+        fonts
+            .load_font_file(config.source_dir.join(font))
+            .unwrap_or_else(|_| panic!("Unable to load font {}", font.to_string_lossy()));
+    }
+    Some(std::sync::Arc::new(fonts))
+}
+
+// Rasterizes the SVG to a PNG
+fn render_social_image(
+    config: &Config,
+    fonts: &SocialImageFonts,
+    image: &SocialImage,
+    path: &Path,
+) -> Vec<u8> {
+    let options = resvg::usvg::Options {
+        resources_dir: Some(config.source_dir.clone()),
+        fontdb: fonts.clone(),
+        ..Default::default()
+    };
+    let tree = resvg::usvg::Tree::from_str(&image.svg, &options)
+        .unwrap_or_else(|e| panic!("Invalid SVG for {}: {}", path.to_string_lossy(), e));
+    let size = tree.size().to_int_size();
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+        .unwrap_or_else(|| panic!("Empty SVG for {}", path.to_string_lossy()));
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::default(),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .encode_png()
+        .unwrap_or_else(|_| panic!("Unable to encode PNG for {}", path.to_string_lossy()))
+}
+
+fn social_image_writer(config: Res<Config>, query: Query<(&AbsoluteOutputPath, &SocialImage)>) {
+    let fonts = match social_image_fonts(&config) {
+        Some(fonts) => fonts,
+        None => return,
+    };
+    query.par_iter().for_each(|(path, image)| {
+        let path = path.path.as_path();
+        let png = render_social_image(&config, &fonts, image, path);
+        std::fs::write(path, png)
+            .unwrap_or_else(|_| panic!("Unable to write output to {}", path.to_string_lossy()));
+    });
 }
 
 fn file_contents_writer(query: Query<(&AbsoluteOutputPath, &WriteContentsToFile)>) {
@@ -1024,6 +3313,183 @@ fn file_contents_writer(query: Query<(&AbsoluteOutputPath, &WriteContentsToFile)
     });
 }
 
+// Only text formats benefit from compression, everything else is already compressed
+const COMPRESSIBLE_EXTENSIONS: [&str; 10] = [
+    "html", "htm", "css", "js", "json", "xml", "txt", "svg", "map", "md",
+];
+
+fn write_compressed(
+    path: &Path,
+    extension: &str,
+    write: impl FnOnce(std::fs::File) -> std::io::Result<()>,
+) {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".");
+    compressed.push(extension);
+    std::fs::File::create(&compressed)
+        .and_then(write)
+        .unwrap_or_else(|_| panic!("Unable to write output to {}", compressed.to_string_lossy()));
+}
+
+fn compressed_output_writer(config: Res<Config>, query: Query<&AbsoluteOutputPath>) {
+    let compression = &config.compression;
+    if !compression.gzip && !compression.brotli {
+        return;
+    }
+    query.par_iter().for_each(|path| {
+        let path = path.path.as_path();
+        let compressible = path
+            .extension()
+            .map(|e| COMPRESSIBLE_EXTENSIONS.contains(&e.to_string_lossy().as_ref()))
+            .unwrap_or(false);
+        if !compressible {
+            return;
+        }
+        let contents = std::fs::read(path)
+            .unwrap_or_else(|_| panic!("Unable to read output {}", path.to_string_lossy()));
+        if compression.gzip {
+            write_compressed(path, "gz", |file| {
+                use std::io::Write;
+                let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::best());
+                encoder.write_all(&contents)?;
+                encoder.finish().map(|_| ())
+            });
+        }
+        if compression.brotli {
+            // The stream writer only finishes on drop, which swallows errors
+            write_compressed(path, "br", |mut file| {
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    lgwin: 22,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut contents.as_slice(), &mut file, &params).map(|_| ())
+            });
+        }
+    });
+}
+
+// Everything a run produced, when kept in memory
+#[derive(Debug, Default)]
+struct InMemorySite {
+    // Keyed by path relative to the output dir
+    files: HashMap<String, axum::body::Bytes>,
+    // Keyed like the RedirectTable
+    redirects: BTreeMap<String, String>,
+}
+
+// Output of the last run, kept in memory instead of written to the output dir
+// Swapped as a whole after each run
+#[derive(Resource, Clone, Debug, Default)]
+struct InMemoryOutput {
+    site: std::sync::Arc<std::sync::RwLock<std::sync::Arc<InMemorySite>>>,
+}
+
+impl InMemoryOutput {
+    fn snapshot(&self) -> std::sync::Arc<InMemorySite> {
+        self.site
+            .read()
+            .expect("In memory output is poisoned!")
+            .clone()
+    }
+
+    fn replace(&self, site: InMemorySite) {
+        *self.site.write().expect("In memory output is poisoned!") = std::sync::Arc::new(site);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn in_memory_output_collector(
+    config: Res<Config>,
+    output: Res<InMemoryOutput>,
+    redirects: Res<GeneratedRedirects>,
+    query: Query<(
+        &AbsoluteOutputPath,
+        Option<&WriteContentsToFile>,
+        Option<&RelativeSourcePath>,
+        Has<CopySourceToOutput>,
+        Option<&SocialImage>,
+    )>,
+) {
+    let fonts = social_image_fonts(&config);
+    let files = std::sync::Mutex::new(HashMap::new());
+    query
+        .par_iter()
+        .for_each(|(path, contents, source, copy, image)| {
+            let path = path.path.as_path();
+            let bytes = match (contents, source, copy, image, fonts.as_ref()) {
+                (Some(contents), _, _, _, _) => contents.contents.clone().into_bytes(),
+                (_, Some(source), true, _, _) => std::fs::read(&source.path).unwrap_or_else(|_| {
+                    panic!("Unable to read file {}", source.path.to_string_lossy())
+                }),
+                (_, _, _, Some(image), Some(fonts)) => {
+                    render_social_image(&config, fonts, image, path)
+                }
+                _ => return,
+            };
+            let key = path
+                .strip_prefix(&config.output_dir)
+                .expect("Output must be inside the output dir")
+                .to_string_lossy()
+                .to_string();
+            files
+                .lock()
+                .expect("Couldn't lock output!")
+                .insert(key, bytes.into());
+        });
+    output.replace(InMemorySite {
+        files: files.into_inner().expect("Couldn't lock output!"),
+        redirects: RedirectTable::keyed(&redirects.entries),
+    });
+}
+
+// Serves the in memory output the way ServeDir serves the output dir
+async fn in_memory_output_handler(
+    axum::extract::State(output): axum::extract::State<InMemoryOutput>,
+    uri: axum::http::Uri,
+) -> axum::response::Response {
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    let site = output.snapshot();
+    // Redirects take priority over their stub pages, like with redirect_middleware
+    if let Some(to) = site.redirects.get(RedirectTable::key(uri.path())) {
+        return (
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, to.clone())],
+        )
+            .into_response();
+    }
+    let files = &site.files;
+    let path = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+    let path = path.trim_start_matches('/');
+    let key = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else if !files.contains_key(path) && files.contains_key(&format!("{}/index.html", path)) {
+        // Directories are only found with a trailing slash
+        let location = format!("{}/", uri.path());
+        return (
+            StatusCode::TEMPORARY_REDIRECT,
+            [(header::LOCATION, location)],
+        )
+            .into_response();
+    } else {
+        path.to_string()
+    };
+    let (status, key) = if files.contains_key(&key) {
+        (StatusCode::OK, key)
+    } else {
+        (StatusCode::NOT_FOUND, "404.html".to_string())
+    };
+    let body = files.get(&key).cloned().unwrap_or_default();
+    let content_type = mime_guess::from_path(&key).first_or_octet_stream();
+    (
+        status,
+        [(header::CONTENT_TYPE, content_type.to_string())],
+        body,
+    )
+        .into_response()
+}
+
 // Process the configs, create the loaders
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct ConfigProcessingStage;
@@ -1032,6 +3498,10 @@ struct ConfigProcessingStage;
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct SourceLoadingStage;
 
+// Compile, minify and fingerprint assets so templates can refer to them
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct AssetProcessingStage;
+
 // Analyzing dynamic content, generates items from each content item
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct AnalyzingDynamicContentStage;
@@ -1044,6 +3514,10 @@ struct IndexingDynamicContentStage;
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct SpawningDynamicContentStage;
 
+// Linking dynamic content into a tree of sections
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct LinkingDynamicContentStage;
+
 // Generating dynamic content
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct GeneratingDynamicContentStage;
@@ -1056,9 +3530,18 @@ struct PreparingForPersistenceStage;
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct PersistOutputStage;
 
-fn run(config: Config) {
-    App::new()
+// Final stage when serving from memory, instead of writing out the output
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct InMemoryOutputStage;
+
+fn run(config: Config, redirects: RedirectTable, output: Option<InMemoryOutput>) {
+    let mut app = App::new();
+    if let Some(output) = output {
+        app.insert_resource(output);
+    }
+    app
         .insert_resource(config)
+        .insert_resource(redirects)
         .add_systems(Update, (
             (
                 create_source_loaders
@@ -1066,41 +3549,76 @@ fn run(config: Config) {
             (
                 static_content_source_loader,
                 template_source_loader,
-                dynamic_content_source_loader
+                dynamic_content_source_loader,
+                asset_source_loader,
+                data_source_loader,
+                not_found_page_loader
             ).in_set(SourceLoadingStage),
+            (
+                asset_processor
+            ).in_set(AssetProcessingStage),
             (
                 generate_urls
             ).in_set(AnalyzingDynamicContentStage),
             (
                 navbar_indexer,
                 blogpost_indexer,
-                sitemap_indexer
+                sitemap_indexer,
+                well_known_files_generator
             ).in_set(IndexingDynamicContentStage),
             (
-                tag_page_generator
+                tag_page_generator,
+                series_page_generator,
+                author_page_generator,
+                data_page_generator
             ).in_set(SpawningDynamicContentStage),
+            (
+                content_tree_builder,
+                section_linker.after(content_tree_builder),
+                redirect_generator,
+                social_image_generator
+            ).in_set(LinkingDynamicContentStage),
             (
                 map_urls_to_relative_paths,
                 dynamic_content_generator
             ).in_set(GeneratingDynamicContentStage),
             (
-                path_absoluter
+                path_absoluter,
+                html_minifier,
+                permalink_conflict_checker
             ).in_set(PreparingForPersistenceStage),
             (
                 output_folder_creator,
                 static_file_copier.after(output_folder_creator),
-                file_contents_writer.after(output_folder_creator)
-            ).in_set(PersistOutputStage)
+                file_contents_writer.after(output_folder_creator),
+                social_image_writer.after(output_folder_creator),
+                compressed_output_writer
+                    .after(static_file_copier)
+                    .after(file_contents_writer),
+                redirect_table_updater
+                    .after(compressed_output_writer)
+                    .after(social_image_writer)
+            ).in_set(PersistOutputStage),
+            (
+                in_memory_output_collector
+            ).in_set(InMemoryOutputStage)
         ))
         .configure_sets(Update, (
             ConfigProcessingStage,
             SourceLoadingStage.after(ConfigProcessingStage),
-            AnalyzingDynamicContentStage.after(SourceLoadingStage),
+            AssetProcessingStage.after(SourceLoadingStage),
+            AnalyzingDynamicContentStage.after(AssetProcessingStage),
             IndexingDynamicContentStage.after(AnalyzingDynamicContentStage),
             SpawningDynamicContentStage.after(IndexingDynamicContentStage),
-            GeneratingDynamicContentStage.after(SpawningDynamicContentStage),
+            LinkingDynamicContentStage.after(SpawningDynamicContentStage),
+            GeneratingDynamicContentStage.after(LinkingDynamicContentStage),
             PreparingForPersistenceStage.after(GeneratingDynamicContentStage),
-            PersistOutputStage.after(PreparingForPersistenceStage)
+            PersistOutputStage
+                .after(PreparingForPersistenceStage)
+                .run_if(not(resource_exists::<InMemoryOutput>)),
+            InMemoryOutputStage
+                .after(PreparingForPersistenceStage)
+                .run_if(resource_exists::<InMemoryOutput>)
         ))
         .run();
 }
@@ -1108,24 +3626,249 @@ fn run(config: Config) {
 #[derive(Debug, StructOpt)]
 #[structopt(name = "suji", about = "Static site generator.")]
 struct Args {
-    #[structopt(help = "Path to config file")]
-    config_path: String,
+    #[structopt(
+        long,
+        short,
+        global = true,
+        help = "Path to config file, defaults to suji.toml, suji.yaml or suji.json"
+    )]
+    config: Option<String>,
 
-    #[structopt(long, help = "Whether to watch for changes.")]
-    watch: bool,
+    #[structopt(flatten)]
+    overrides: ConfigOverrides,
 
-    #[structopt(long, help = "Whether to serve output directory.")]
-    serve: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
 
-    #[structopt(long, help = "Port to bind.", default_value = "8000")]
-    port: u16,
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(about = "Build the site once.")]
+    Build,
+    #[structopt(about = "Build the site, and rebuild it whenever a source changes.")]
+    Watch,
+    #[structopt(about = "Build and serve the site, rebuilding it whenever a source changes.")]
+    Serve {
+        #[structopt(
+            long,
+            help = "Address to bind, e.g. 0.0.0.0 to preview from other devices on the network.",
+            default_value = "127.0.0.1"
+        )]
+        host: IpAddr,
+
+        #[structopt(
+            long,
+            help = "Port to bind, the next free one is used if it's taken.",
+            default_value = "8000"
+        )]
+        port: u16,
+
+        #[structopt(long, help = "Don't rebuild when sources change.")]
+        no_watch: bool,
+
+        #[structopt(
+            long,
+            help = "Keep the site in memory and serve it from there, instead of writing it out."
+        )]
+        in_memory: bool,
+    },
+    #[structopt(about = "Check the config, sources, routes and templates without building.")]
+    Check,
+    #[structopt(about = "Work with the config file.")]
+    Config(ConfigCommand),
+    #[structopt(about = "Create new content.")]
+    New(NewCommand),
+    #[structopt(about = "Create a new site.")]
+    Init {
+        #[structopt(help = "Directory to create the site in.", default_value = ".")]
+        path: PathBuf,
+    },
 }
 
-fn get_config_from_path(path: &str) -> Config {
-    let source = std::fs::read_to_string(path)
-        .unwrap_or_else(|_| panic!("Unable to find config file {}", path));
-    let mut config: Config =
-        serde_json::from_str(&source).expect("Config is not in the expected format!");
+#[derive(Debug, StructOpt)]
+enum ConfigCommand {
+    #[structopt(about = "Same as suji check.")]
+    Check,
+}
+
+#[derive(Debug, StructOpt)]
+enum NewCommand {
+    #[structopt(about = "Create a blogpost dated today.")]
+    Post {
+        #[structopt(help = "Title of the post.")]
+        title: String,
+    },
+}
+
+// Ways to change the config from the command line
+#[derive(Debug, Clone, StructOpt)]
+struct ConfigOverrides {
+    #[structopt(
+        long,
+        global = true,
+        env = "SUJI_ENV",
+        help = "Profile from the config to apply."
+    )]
+    env: Option<String>,
+
+    #[structopt(long, global = true, help = "Override the site URL.")]
+    site_url: Option<String>,
+
+    #[structopt(long, global = true, help = "Override the output directory.")]
+    output_dir: Option<PathBuf>,
+}
+
+// Recursively merges objects, anything else is replaced by the overlay
+fn merge_config(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_config(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// An environment variable setting a config value:
+// SUJI_FOO=bar sets foo, SUJI_FOO__BAR=baz sets foo.bar
+struct ConfigEnvVar {
+    name: String,
+    path: Vec<String>,
+    value: Value,
+}
+
+impl ConfigEnvVar {
+    // Values are parsed as TOML, anything that isn't valid TOML is a string
+    fn parse_value(value: &str) -> Value {
+        toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .filter(|value| !value.is_datetime())
+            .and_then(|value| serde_json::to_value(value).ok())
+            .unwrap_or_else(|| Value::String(value.to_string()))
+    }
+
+    fn overlay(&self) -> Value {
+        self.path.iter().rev().fold(
+            self.value.clone(),
+            |value, part| serde_json::json!({ part: value }),
+        )
+    }
+
+    // Whether an error at this path of the config comes from this variable
+    fn sets(&self, path: &str) -> bool {
+        let own = self.path.join(".");
+        path == own || path.starts_with(&format!("{}.", own))
+    }
+}
+
+fn config_env_vars() -> Vec<ConfigEnvVar> {
+    std::env::vars()
+        // SUJI_ENV picks the profile, rather than setting a value
+        .filter(|(name, _)| name != "SUJI_ENV")
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix("SUJI_")?.to_lowercase();
+            let path = key.split("__").map(|part| part.to_string()).collect();
+            let value = ConfigEnvVar::parse_value(&value);
+            Some(ConfigEnvVar { name, path, value })
+        })
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .collect()
+}
+
+// Used when no config file is given
+const DEFAULT_CONFIG_PATHS: [&str; 4] = ["suji.toml", "suji.yaml", "suji.yml", "suji.json"];
+
+fn find_config_path(path: Option<&String>) -> Result<String, String> {
+    match path {
+        Some(path) => Ok(path.clone()),
+        None => DEFAULT_CONFIG_PATHS
+            .iter()
+            .find(|p| Path::new(p).is_file())
+            .map(|p| p.to_string())
+            .ok_or_else(|| {
+                format!(
+                    "No config file given, and none of {} exist!",
+                    DEFAULT_CONFIG_PATHS.join(", ")
+                )
+            }),
+    }
+}
+
+// Default config for sites using a theme, in the theme directory
+const THEME_CONFIG_NAMES: [&str; 4] = ["theme.toml", "theme.yaml", "theme.yml", "theme.json"];
+
+fn find_theme_config(theme: &Path) -> Option<PathBuf> {
+    THEME_CONFIG_NAMES
+        .iter()
+        .map(|name| theme.join(name))
+        .find(|path| path.is_file())
+}
+
+// Applied in order: the theme config, the config file, the profile, environment variables and then the command line
+fn get_config_from_path(path: &str, overrides: &ConfigOverrides) -> Result<Config, String> {
+    let mut value = parse_data_file(Path::new(path))?;
+    // The theme's config provides defaults for anything the site doesn't set, profiles included
+    let theme = value
+        .get("theme")
+        .and_then(|t| t.as_str())
+        .map(PathBuf::from);
+    if let Some(theme) = theme.as_ref() {
+        if !theme.is_dir() {
+            return Err(format!(
+                "Theme {} used by {} is not a directory!",
+                theme.to_string_lossy(),
+                path
+            ));
+        }
+        if let Some(theme_config) = find_theme_config(theme) {
+            let mut defaults = parse_data_file(&theme_config)?;
+            merge_config(&mut defaults, value);
+            value = defaults;
+        }
+    }
+    let profiles = value
+        .as_object_mut()
+        .and_then(|config| config.remove("profiles"))
+        .unwrap_or_default();
+    if let Some(env) = overrides.env.as_ref() {
+        let profile = profiles
+            .get(env)
+            .ok_or_else(|| format!("No profile named {} in {}!", env, path))?;
+        merge_config(&mut value, profile.clone());
+    }
+    let env_vars = config_env_vars();
+    for var in &env_vars {
+        merge_config(&mut value, var.overlay());
+    }
+    if let Some(site_url) = overrides.site_url.as_ref() {
+        merge_config(&mut value, serde_json::json!({ "site_url": site_url }));
+    }
+    if let Some(output_dir) = overrides.output_dir.as_ref() {
+        merge_config(&mut value, serde_json::json!({ "output_dir": output_dir }));
+    }
+    let mut config: Config = serde_path_to_error::deserialize(value).map_err(|e| {
+        let error_path = e.path().to_string();
+        match env_vars
+            .iter()
+            .filter(|var| var.sets(&error_path))
+            .max_by_key(|var| var.path.len())
+        {
+            Some(var) => format!(
+                "Invalid value in {} at {}: {}",
+                var.name,
+                error_path,
+                e.inner()
+            ),
+            None => format!(
+                "Invalid config in {} at {}: {}",
+                path,
+                error_path,
+                e.inner()
+            ),
+        }
+    })?;
     let cwd = std::env::current_dir().expect("Couldn't get current dir!");
 
     // TODO: Verify config is inside source dir
@@ -1138,13 +3881,449 @@ fn get_config_from_path(path: &str) -> Config {
     if config.output_dir.is_relative() {
         config.output_dir = cwd.join(config.output_dir);
     }
-    config
+    for (id, author) in config.authors.iter_mut() {
+        author.id.clone_from(id);
+    }
+    if let Some(theme) = config.theme.as_mut() {
+        *theme = theme
+            .canonicalize()
+            .map_err(|e| format!("Couldn't find theme {}: {}", theme.to_string_lossy(), e))?;
+    }
+    config.profile.clone_from(&overrides.env);
+    Ok(config)
+}
+
+fn check_glob(glob: &str, problems: &mut Vec<String>) {
+    match glob::glob(glob) {
+        Ok(mut paths) => {
+            if !paths.any(|p| p.is_ok()) {
+                problems.push(format!("Glob {} doesn't match any files", glob));
+            }
+        }
+        Err(e) => problems.push(format!("Invalid glob {}: {}", glob, e)),
+    }
+}
+
+// Validates everything that can be checked without building, returning the problems found
+fn check_config(config: &Config) -> Vec<String> {
+    let mut problems = vec![];
+    for glob in config.sources.keys().sorted() {
+        check_glob(glob, &mut problems);
+    }
+    for globs in config.assets.bundles.values() {
+        for glob in globs {
+            check_glob(glob, &mut problems);
+        }
+    }
+    // Every placeholder gets a dummy value, so only the syntax and filters are checked
+    let dummy = Value::String("x".to_string());
+    for (route, pattern) in config.routes.iter().sorted() {
+        if let Err(e) = expand_permalink(pattern, |_| Some(&dummy)) {
+            problems.push(format!("Invalid route {} ({}): {}", route, pattern, e));
+        }
+    }
+    let template_globs = config
+        .sources
+        .iter()
+        .filter(|(_, source)| matches!(source, SourceType::Template))
+        .map(|(glob, _)| glob)
+        .sorted();
+    let tera = load_templates(config, template_globs).unwrap_or_else(|e| {
+        problems.push(e);
+        Tera::default()
+    });
+    let check_template = |template: &str, user: &str| {
+        let exists = tera.get_template_names().any(|t| t == template);
+        (!exists).then(|| format!("Unknown template {} used by {}", template, user))
+    };
+    problems.extend(check_template(
+        &config.blogpost_template,
+        "blogpost_template",
+    ));
+    if let Some(template) = config.not_found_template.as_ref() {
+        problems.extend(check_template(template, "not_found_template"));
+    }
+    if let Some(social) = config.social_images.as_ref() {
+        problems.extend(check_template(&social.template, "social_images"));
+    }
+    let dynamic_globs = config.sources.iter().filter(|(_, source)| {
+        !matches!(
+            source,
+            SourceType::StaticContent | SourceType::Template | SourceType::Asset | SourceType::Data
+        )
+    });
+    let paths: Vec<_> = dynamic_globs
+        .filter_map(|(glob, _)| glob::glob(glob).ok())
+        .flat_map(|paths| paths.filter_map(|p| p.ok()))
+        .sorted()
+        .collect();
+    for path in paths {
+        let user = path.to_string_lossy().to_string();
+        match read_dynamic_content(&path) {
+            Ok((metadata, _)) => {
+                if !config.routes.contains_key(&metadata.route) {
+                    problems.push(format!("Unknown route {} used by {}", metadata.route, user));
+                }
+                if let Some(template) = metadata.template.as_ref() {
+                    problems.extend(check_template(template, &user));
+                }
+            }
+            Err(e) => problems.push(e),
+        }
+    }
+    problems
+}
+
+// Problems loading the config are reported like any other
+fn check_site(loaded: Result<(String, Config), String>) {
+    let (config_path, problems) = match loaded {
+        Ok((config_path, config)) => (config_path, check_config(&config)),
+        Err(e) => (String::new(), vec![e]),
+    };
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        std::process::exit(1);
+    }
+    println!("{} is valid.", config_path);
+}
+
+// Posts go next to the others, named after the title, with the same route
+fn new_post(config: &Config, title: &str) {
+    let glob = config
+        .sources
+        .iter()
+        .filter(|(_, source)| matches!(source, SourceType::DynamicContentBlogPost))
+        .map(|(glob, _)| glob)
+        .sorted()
+        .next()
+        .unwrap_or_else(|| panic!("No blogpost sources in the config!"));
+    // Everything up to the first wildcard
+    let dir: PathBuf = Path::new(glob)
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect();
+    let extension = Path::new(glob)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .filter(|e| !e.contains(['*', '?', '[']))
+        .unwrap_or_else(|| "md".to_string());
+    let existing = glob::glob(glob)
+        .ok()
+        .and_then(|mut paths| paths.find_map(|p| p.ok()))
+        .and_then(|path| read_dynamic_content(&path).ok());
+    let route = match existing {
+        Some((metadata, _)) => metadata.route,
+        // Other routes with a {slug} could be for anything, so don't guess
+        None if config.routes.contains_key("blogpost") => "blogpost".to_string(),
+        None => {
+            panic!("There are no posts to copy the route from, add a blogpost route for new posts!")
+        }
+    };
+    let slug = slugify(title);
+    if slug.is_empty() {
+        panic!("Title {} has nothing to make a file name from!", title);
+    }
+    let path = dir.join(format!("{}.{}", slug, extension));
+    if path.exists() {
+        panic!("{} already exists!", path.to_string_lossy());
+    }
+    let metadata = serde_json::json!({
+        "route": route,
+        "title": title,
+        "date": chrono::Local::now().format("%Y/%m/%d").to_string(),
+        "tags": [],
+    });
+    let contents = format!(
+        "{}\n\nWrite your post here.\n",
+        serde_json::to_string_pretty(&metadata).expect("Couldn't serialize metadata!")
+    );
+    std::fs::create_dir_all(&dir)
+        .unwrap_or_else(|_| panic!("Could not create directory: {}", dir.to_string_lossy()));
+    std::fs::write(&path, contents)
+        .unwrap_or_else(|_| panic!("Unable to write output to {}", path.to_string_lossy()));
+    println!("Created {}", path.to_string_lossy());
+}
+
+// Files for a new site, relative to its directory. The starter site is
+// checked in, so it can be built and kept working like any other site.
+const SITE_SKELETON: [(&str, &str); 20] = [
+    ("suji.toml", include_str!("../starter/suji.toml")),
+    (".gitignore", include_str!("../starter/.gitignore")),
+    (
+        "assets/css/site.css",
+        include_str!("../starter/assets/css/site.css"),
+    ),
+    (
+        "data/projects.json",
+        include_str!("../starter/data/projects.json"),
+    ),
+    ("feeds/rss.xml", include_str!("../starter/feeds/rss.xml")),
+    (
+        "feeds/sitemap.xml",
+        include_str!("../starter/feeds/sitemap.xml"),
+    ),
+    (
+        "generators/author.html",
+        include_str!("../starter/generators/author.html"),
+    ),
+    (
+        "generators/project.html",
+        include_str!("../starter/generators/project.html"),
+    ),
+    (
+        "generators/series.html",
+        include_str!("../starter/generators/series.html"),
+    ),
+    (
+        "generators/tag.html",
+        include_str!("../starter/generators/tag.html"),
+    ),
+    (
+        "listings/archive.html",
+        include_str!("../starter/listings/archive.html"),
+    ),
+    (
+        "pages/about.html",
+        include_str!("../starter/pages/about.html"),
+    ),
+    (
+        "pages/index.html",
+        include_str!("../starter/pages/index.html"),
+    ),
+    (
+        "posts/hello-world.md",
+        include_str!("../starter/posts/hello-world.md"),
+    ),
+    (
+        "static/favicon.svg",
+        include_str!("../starter/static/favicon.svg"),
+    ),
+    (
+        "templates/404.html",
+        include_str!("../starter/templates/404.html"),
+    ),
+    (
+        "templates/base.html",
+        include_str!("../starter/templates/base.html"),
+    ),
+    (
+        "templates/blogpost.html",
+        include_str!("../starter/templates/blogpost.html"),
+    ),
+    (
+        "templates/macros.html",
+        include_str!("../starter/templates/macros.html"),
+    ),
+    (
+        "templates/shortcodes/note.html",
+        include_str!("../starter/templates/shortcodes/note.html"),
+    ),
+];
+
+fn init_site(dir: &Path) {
+    for (path, _) in SITE_SKELETON {
+        if dir.join(path).exists() {
+            panic!("{} already exists!", dir.join(path).to_string_lossy());
+        }
+    }
+    for (path, contents) in SITE_SKELETON {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap_or_else(|_| {
+                panic!("Could not create directory: {}", parent.to_string_lossy())
+            });
+        }
+        std::fs::write(&path, contents)
+            .unwrap_or_else(|_| panic!("Unable to write output to {}", path.to_string_lossy()));
+    }
+    println!(
+        "Created a new site in {}, run suji serve there to see it.",
+        dir.to_string_lossy()
+    );
+}
+
+// Config of a watched site, None once it's stopped
+type WatchedConfig = std::sync::Arc<std::sync::Mutex<Option<Config>>>;
+
+// Regenerates the site whenever a source changes, until stopped
+struct SiteWatcher {
+    watcher: Hotwatch,
+    config: WatchedConfig,
+}
+
+impl SiteWatcher {
+    // Waits for a generation in progress to finish, so the output isn't left half written
+    fn stop(self) {
+        self.config.lock().expect("Couldn't lock config!").take();
+        drop(self.watcher);
+    }
+}
+
+// Reruns generation whenever a source (or the config) changes
+fn watch(
+    config_path_str: String,
+    overrides: ConfigOverrides,
+    config: Config,
+    redirects: RedirectTable,
+    output: Option<InMemoryOutput>,
+    logger: slog::Logger,
+) -> SiteWatcher {
+    let config_path = PathBuf::from(&config_path_str)
+        .canonicalize()
+        .expect("Couldn't canonicalize config path!");
+    let source_dir = config.source_dir.clone();
+    let output_dir = config.output_dir.clone();
+    // The theme can live outside the site
+    let dirs: Vec<_> = std::iter::once(source_dir.clone())
+        .chain(
+            config
+                .theme
+                .clone()
+                .filter(|theme| !theme.starts_with(&source_dir)),
+        )
+        .collect();
+    let config: WatchedConfig = std::sync::Arc::new(std::sync::Mutex::new(Some(config)));
+    let mut watcher = Hotwatch::new().expect("Couldn't create watcher!");
+    for dir in dirs {
+        let config_path = config_path.clone();
+        let config_path_str = config_path_str.clone();
+        let overrides = overrides.clone();
+        let output_dir = output_dir.clone();
+        let config = config.clone();
+        let redirects = redirects.clone();
+        let output = output.clone();
+        let logger = logger.clone();
+        watcher
+            .watch(&dir, move |event| {
+                if !(event.kind.is_modify() || event.kind.is_create()) {
+                    return;
+                }
+                let should_reload = event.paths.iter().any(|p| p == config_path.as_path());
+                let should_rerun = event.paths.iter().any(|p| !p.starts_with(&output_dir));
+                let mut config = config.lock().expect("Couldn't lock config!");
+                let Some(config) = config.as_mut() else {
+                    return;
+                };
+                if should_reload {
+                    info!(logger, "Reloading config...");
+                    match get_config_from_path(&config_path_str, &overrides) {
+                        Ok(new) => *config = new,
+                        Err(e) => {
+                            error!(logger, "Error reloading config:"; "error" => e);
+                            return;
+                        }
+                    }
+                }
+                if should_reload || should_rerun {
+                    info!(logger, "Rerunning generation..."; "event" => ?event);
+                    if let Err(e) = std::panic::catch_unwind(|| {
+                        run(config.clone(), redirects.clone(), output.clone())
+                    }) {
+                        error!(logger, "Error running generation:"; "error" => ?e);
+                    }
+                }
+            })
+            .expect("Couldn't watch!");
+    }
+    info!(logger, "Watcher successfully set up...");
+    SiteWatcher { watcher, config }
+}
+
+// How many ports after the requested one to try if it's taken
+const PORT_FALLBACK_ATTEMPTS: u16 = 20;
+
+async fn bind_with_fallback(
+    host: IpAddr,
+    port: u16,
+    logger: &slog::Logger,
+) -> tokio::net::TcpListener {
+    let last = port.saturating_add(PORT_FALLBACK_ATTEMPTS);
+    for port in port..=last {
+        match tokio::net::TcpListener::bind((host, port)).await {
+            Ok(listener) => return listener,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && port < last => {
+                warn!(logger, "Port is in use, trying the next one"; "port" => port);
+            }
+            Err(e) => panic!("Unable to listen on {}:{}: {}", host, port, e),
+        }
+    }
+    unreachable!()
+}
+
+async fn shutdown_signal(logger: slog::Logger) {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Couldn't listen for Ctrl-C!");
+    info!(logger, "Shutting down...");
+}
+
+async fn serve(
+    config: &Config,
+    host: IpAddr,
+    port: u16,
+    redirects: RedirectTable,
+    output: Option<InMemoryOutput>,
+    logger: &slog::Logger,
+) {
+    let mut serve_dir = ServeDir::new(config.output_dir.clone());
+    // Missing pages get the rendered 404 page, or an empty 404 if there isn't one
+    let mut not_found = ServeFile::new(config.output_dir.join("404.html"));
+    if config.compression.gzip {
+        serve_dir = serve_dir.precompressed_gzip();
+        not_found = not_found.precompressed_gzip();
+    }
+    if config.compression.brotli {
+        serve_dir = serve_dir.precompressed_br();
+        not_found = not_found.precompressed_br();
+    }
+    let serve_dir = serve_dir.not_found_service(not_found);
+    let redirect_layer = axum::middleware::from_fn_with_state(redirects, redirect_middleware);
+    let app = match output {
+        // Redirects are part of the in memory output, so they change along with it
+        Some(output) => Router::new()
+            .fallback(in_memory_output_handler)
+            .with_state(output),
+        None => Router::new()
+            .nest_service("/", serve_dir)
+            .layer(redirect_layer),
+    };
+    let listener = bind_with_fallback(host, port, logger).await;
+    let addr = listener.local_addr().expect("Couldn't get server address!");
+    info!(logger, "Setup HTTP server to listen on"; "url" => format!("http://{}/", addr));
+    if host.is_unspecified() {
+        info!(
+            logger,
+            "Listening on all interfaces, use this machine's address to preview from other devices"
+        );
+    }
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(logger.clone()))
+        .await
+        .expect("HTTP server failed!");
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::from_args();
-    let config = get_config_from_path(&args.config_path);
+    // The only command that doesn't need a config
+    if let Command::Init { path } = &args.command {
+        init_site(path);
+        return;
+    }
+    let loaded = find_config_path(args.config.as_ref()).and_then(|path| {
+        let config = get_config_from_path(&path, &args.overrides)?;
+        Ok((path, config))
+    });
+    if let Command::Check | Command::Config(ConfigCommand::Check) = &args.command {
+        check_site(loaded);
+        return;
+    }
+    let (config_path, config) = loaded.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
 
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
@@ -1152,51 +4331,96 @@ async fn main() {
 
     let logger = slog::Logger::root(drain, o!());
 
-    info!(logger, "Running initial generation...");
-    run(config.clone());
+    let redirects = RedirectTable::default();
+    match args.command {
+        Command::New(NewCommand::Post { title }) => new_post(&config, &title),
+        Command::Build => {
+            info!(logger, "Running generation...");
+            run(config, redirects, None);
+        }
+        Command::Watch => {
+            info!(logger, "Running initial generation...");
+            run(config.clone(), redirects.clone(), None);
+            let watcher = watch(
+                config_path,
+                args.overrides,
+                config,
+                redirects,
+                None,
+                logger.clone(),
+            );
+            shutdown_signal(logger).await;
+            watcher.stop();
+        }
+        Command::Serve {
+            host,
+            port,
+            no_watch,
+            in_memory,
+        } => {
+            let output = in_memory.then(InMemoryOutput::default);
+            info!(logger, "Running initial generation...");
+            run(config.clone(), redirects.clone(), output.clone());
+            let watcher = (!no_watch).then(|| {
+                watch(
+                    config_path,
+                    args.overrides,
+                    config.clone(),
+                    redirects.clone(),
+                    output.clone(),
+                    logger.clone(),
+                )
+            });
+            serve(&config, host, port, redirects, output, &logger).await;
+            if let Some(watcher) = watcher {
+                watcher.stop();
+            }
+        }
+        Command::Init { .. } | Command::Check | Command::Config(ConfigCommand::Check) => {
+            unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if args.watch {
-        let config_path_str = args.config_path.clone();
-        let config_path = PathBuf::from(&config_path_str)
-            .canonicalize()
-            .expect("Couldn't canonicalize config path!");
-        let mut config = config.clone();
-        let source_dir = config.source_dir.clone();
-        let output_dir = config.output_dir.clone();
-        let logger = logger.clone();
-        tokio::task::spawn_blocking(move || {
-            let logger2 = logger.clone();
-            let mut watcher = Hotwatch::new().expect("Couldn't create watcher!");
-            watcher
-                .watch(&source_dir, move |event| {
-                    if !(event.kind.is_modify() || event.kind.is_create()) {
-                        return Flow::Continue;
-                    }
-                    let should_reload = event.paths.iter().any(|p| p == config_path.as_path());
-                    let should_rerun = event.paths.iter().any(|p| !p.starts_with(&output_dir));
-                    if should_reload {
-                        info!(logger2, "Reloading config...");
-                        config = get_config_from_path(&config_path_str);
-                    }
-                    if should_reload || should_rerun {
-                        info!(logger2, "Rerunning generation..."; "event" => ?event);
-                        if let Err(e) = std::panic::catch_unwind(|| run(config.clone())) {
-                            error!(logger2, "Error running generation:"; "error" => ?e);
-                        }
-                    }
-                    Flow::Continue
-                })
-                .expect("Couldn't watch!");
-            info!(logger, "Watcher successfully set up...");
-            watcher.run();
-        });
+    #[test]
+    fn parse_shortcodes_skips_code() {
+        let contents = "`{{< a >}}`\n\n```\n{{< b >}}x{{< /b >}}\n```\n\n{{< c >}}y{{< /c >}}\n";
+        let shortcodes = parse_shortcodes(contents);
+        assert_eq!(shortcodes.len(), 1);
+        assert_eq!(shortcodes[0].name, "c");
+        assert_eq!(shortcodes[0].body.as_deref(), Some("y"));
+    }
+
+    #[test]
+    fn minify_html_keeps_non_ascii_text() {
+        assert_eq!(
+            minify_html("<p>Été  à\n Paris</p>\n<p>ünïcödé</p>"),
+            "<p>Été à Paris</p> <p>ünïcödé</p>"
+        );
+    }
+
+    #[test]
+    fn minify_html_preserves_raw_elements() {
+        let html = "<div>\n  <pre>  a\n    b </pre>\n  <TEXTAREA>  x\n\n y</TEXTAREA>\n</div>";
+        assert_eq!(
+            minify_html(html),
+            "<div> <pre>  a\n    b </pre> <TEXTAREA>  x\n\n y</TEXTAREA> </div>"
+        );
     }
 
-    if args.serve {
-        let app = Router::new().nest_service("/", ServeDir::new(config.output_dir.clone()));
-        let addr = SocketAddr::from(([127, 0, 0, 1], args.port));
-        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-        info!(logger, "Setup HTTP server to listen on"; "port" => args.port);
-        axum::serve(listener, app).await.unwrap();
+    #[test]
+    fn minify_html_quotes_attribute_values() {
+        assert_eq!(
+            minify_html(r#"<a  href="/x"  title='say "hi"'   data-x="it's">"#),
+            r#"<a href=/x title='say "hi"' data-x="it's">"#
+        );
+        assert_eq!(
+            minify_html(r#"<a title=a"b'c">"#),
+            r#"<a title="a&quot;b'c&quot;">"#
+        );
     }
 }